@@ -0,0 +1,154 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// An unsigned integer of any size, with only the arithmetic fuel needs.
+// Limbs are base 2^32, least significant first, with no trailing zeros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigMass {
+    limbs: Vec<u32>,
+}
+
+impl BigMass {
+    pub fn from_u64(value: u64) -> BigMass {
+        let mut result = BigMass { limbs: vec![value as u32, (value >> 32) as u32] };
+        result.normalize();
+        result
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn normalize(&mut self) {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+
+    fn mul_add(&mut self, factor: u32, addend: u32) {
+        let mut carry = addend as u64;
+        for limb in self.limbs.iter_mut() {
+            let value = *limb as u64 * factor as u64 + carry;
+            *limb = value as u32;
+            carry = value >> 32;
+        }
+        if carry != 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    pub fn div_rem(&self, divisor: u64) -> (BigMass, u64) {
+        assert!(divisor != 0, "Division by zero");
+
+        let mut limbs = vec![0; self.limbs.len()];
+        let mut rem = 0u128;
+        for (idx, &limb) in self.limbs.iter().enumerate().rev() {
+            let value = (rem << 32) | limb as u128;
+            limbs[idx] = (value / divisor as u128) as u32;
+            rem = value % divisor as u128;
+        }
+
+        let mut quotient = BigMass { limbs };
+        quotient.normalize();
+        (quotient, rem as u64)
+    }
+
+    // None if `amount` is bigger than self
+    pub fn checked_sub(&self, amount: u64) -> Option<BigMass> {
+        if *self < BigMass::from_u64(amount) {
+            return None;
+        }
+
+        let mut limbs = self.limbs.clone();
+        let mut borrow = amount as u128;
+        for limb in limbs.iter_mut() {
+            if borrow == 0 {
+                break;
+            }
+            let take = borrow & 0xffff_ffff;
+            borrow >>= 32;
+            if (*limb as u128) < take {
+                *limb = ((1u128 << 32) + *limb as u128 - take) as u32;
+                borrow += 1;
+            } else {
+                *limb -= take as u32;
+            }
+        }
+
+        let mut result = BigMass { limbs };
+        result.normalize();
+        Some(result)
+    }
+
+    pub fn add(&self, other: &BigMass) -> BigMass {
+        let len = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::with_capacity(len + 1);
+        let mut carry = 0u64;
+
+        for idx in 0..len {
+            let sum = *self.limbs.get(idx).unwrap_or(&0) as u64 + *other.limbs.get(idx).unwrap_or(&0) as u64 + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+
+        let mut result = BigMass { limbs };
+        result.normalize();
+        result
+    }
+}
+
+impl Ord for BigMass {
+    fn cmp(&self, other: &BigMass) -> Ordering {
+        self.limbs.len().cmp(&other.limbs.len()).then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigMass {
+    fn partial_cmp(&self, other: &BigMass) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for BigMass {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<BigMass, String> {
+        if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid mass {:?}", text));
+        }
+
+        let mut result = BigMass { limbs: Vec::new() };
+        for digit in text.bytes() {
+            result.mul_add(10, (digit - b'0') as u32);
+        }
+        result.normalize();
+
+        Ok(result)
+    }
+}
+
+impl fmt::Display for BigMass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time, least significant first
+        let mut chunks = Vec::new();
+        let mut rest = self.clone();
+        while !rest.is_zero() {
+            let (quotient, chunk) = rest.div_rem(1_000_000_000);
+            chunks.push(chunk);
+            rest = quotient;
+        }
+
+        write!(f, "{}", chunks.last().unwrap())?;
+        for chunk in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", chunk)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::big::BigMass;
+use crate::model::FuelModel;
+
+// The arithmetic fuel needs, so masses can be u128 or as big as they like
+pub trait Mass: Clone + Ord + fmt::Display {
+    fn zero() -> Self;
+    fn from_u64(value: u64) -> Self;
+    fn div_rem(&self, divisor: u64) -> (Self, u64);
+    // None if the result would be negative
+    fn checked_sub(&self, amount: u64) -> Option<Self>;
+    // None if the result doesn't fit
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl Mass for u128 {
+    fn zero() -> u128 {
+        0
+    }
+
+    fn from_u64(value: u64) -> u128 {
+        value as u128
+    }
+
+    fn div_rem(&self, divisor: u64) -> (u128, u64) {
+        (self / divisor as u128, (self % divisor as u128) as u64)
+    }
+
+    fn checked_sub(&self, amount: u64) -> Option<u128> {
+        u128::checked_sub(*self, amount as u128)
+    }
+
+    fn checked_add(&self, other: &u128) -> Option<u128> {
+        u128::checked_add(*self, *other)
+    }
+}
+
+impl Mass for BigMass {
+    fn zero() -> BigMass {
+        BigMass::from_u64(0)
+    }
+
+    fn from_u64(value: u64) -> BigMass {
+        BigMass::from_u64(value)
+    }
+
+    fn div_rem(&self, divisor: u64) -> (BigMass, u64) {
+        BigMass::div_rem(self, divisor)
+    }
+
+    fn checked_sub(&self, amount: u64) -> Option<BigMass> {
+        BigMass::checked_sub(self, amount)
+    }
+
+    fn checked_add(&self, other: &BigMass) -> Option<BigMass> {
+        Some(self.add(other))
+    }
+}
+
+// What to do with a module too light to need fuel, where its fuel goes negative
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Negative {
+    Clamp,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FuelError {
+    // The module's mass, as text so it works for any size
+    Negative(String),
+    Overflow,
+    // A model whose fuel for fuel never runs out, as text like Negative
+    Endless(String),
+}
+
+impl fmt::Display for FuelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FuelError::Negative(mass) => write!(f, "mass {} is too small, its fuel would be negative", mass),
+            FuelError::Overflow => write!(f, "total fuel is too big, try big masses"),
+            FuelError::Endless(mass) => write!(f, "fuel for mass {} never stops needing more fuel", mass),
+        }
+    }
+}
+
+// The fuel for one module, then the fuel for that fuel and so on, stopping
+// before the first stage that needs none
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakdown<M> {
+    pub mass: M,
+    pub stages: Vec<M>,
+}
+
+impl<M: Mass> Breakdown<M> {
+    pub fn new(mass: M, model: &FuelModel, negative: Negative) -> Result<Breakdown<M>, FuelError> {
+        let mut stages = Vec::new();
+
+        let mut next = match (model.fuel(&mass), negative) {
+            (Some(direct), _) => direct,
+            (None, Negative::Clamp) => M::zero(),
+            (None, Negative::Error) => return Err(FuelError::Negative(mass.to_string())),
+        };
+
+        while next > M::zero() {
+            let after = model.fuel(&next).unwrap_or_else(M::zero);
+            let done = !model.recursive || after == M::zero();
+
+            // With the standard model each stage is under a third of the one
+            // before, but a model that doesn't shrink the fuel would never finish
+            if !done && after >= next {
+                return Err(FuelError::Endless(mass.to_string()));
+            }
+
+            stages.push(next);
+            next = if done { M::zero() } else { after };
+        }
+
+        Ok(Breakdown { mass, stages })
+    }
+
+    // The fuel for the module alone
+    pub fn direct(&self) -> M {
+        self.stages.first().cloned().unwrap_or_else(M::zero)
+    }
+
+    // Every stage added up, or None if that doesn't fit
+    pub fn total(&self) -> Option<M> {
+        self.stages.iter().try_fold(M::zero(), |total, stage| total.checked_add(stage))
+    }
+
+    pub fn depth(&self) -> usize {
+        self.stages.len()
+    }
+}
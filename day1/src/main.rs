@@ -1,26 +1,139 @@
-fn get_fuel(mass: isize) -> isize {
-    (mass / 3) - 2
+mod big;
+mod fuel;
+mod model;
+mod report;
+mod stream;
+#[cfg(test)]
+mod tests;
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::str::FromStr;
+
+use big::BigMass;
+use fuel::{Breakdown, FuelError, Mass, Negative};
+use model::FuelModel;
+use report::{Format, Report};
+use stream::{BadLine, BadLines, Module, Stream};
+
+fn total_fuel<M: Mass>(breakdowns: &[Module<M>], show: bool) -> Result<M, String> {
+    let mut total = M::zero();
+
+    for (_, breakdown) in breakdowns.iter() {
+        let fuel = breakdown.total().ok_or_else(|| FuelError::Overflow.to_string())?;
+
+        if show {
+            let stages = breakdown.stages.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            println!(
+                "{}: direct {}, {} stages [{}], total {}",
+                breakdown.mass, breakdown.direct(), breakdown.depth(), stages.join(", "), fuel,
+            );
+        }
+
+        total = total.checked_add(&fuel).ok_or_else(|| FuelError::Overflow.to_string())?;
+    }
+
+    Ok(total)
+}
+
+fn warn_skipped(skipped: &[BadLine]) {
+    for bad in skipped.iter() {
+        eprintln!("Skipped {}", bad);
+    }
 }
 
-fn get_recursive_fuel(mass: isize) -> isize {
-    let mut total = mass;
-    let mut next_fuel = get_fuel(total);
-    while next_fuel > 0 {
-        total += next_fuel;
-        next_fuel = get_fuel(next_fuel);
+// The total fuel, or the whole report if one was asked for. Only a report or
+// a breakdown needs every module in memory, otherwise the masses stream past.
+fn summarize<M>(path: &str, stream: &Stream, show: bool, report: Option<Format>) -> Result<String, String>
+    where M: Mass + FromStr + Send, M::Err: Display
+{
+    let reader = BufReader::new(File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?);
+
+    if report.is_none() && !show {
+        let summary = stream.total::<M, _>(reader).map_err(|e| e.to_string())?;
+        warn_skipped(&summary.skipped);
+        return Ok(summary.total.to_string());
     }
 
-    total - mass
+    let (breakdowns, skipped) = stream.breakdowns::<M, _>(reader).map_err(|e| e.to_string())?;
+    warn_skipped(&skipped);
+
+    match report {
+        Some(format) => Report::new(&breakdowns).map(|r| r.render(format)).map_err(|e| e.to_string()),
+        None => total_fuel(&breakdowns, show).map(|t| t.to_string()),
+    }
 }
 
 fn main() {
-    assert_eq!(get_recursive_fuel(1969), 966);
-    let result = std::fs::read_to_string("data.text")
-        .unwrap()
-        .lines()
-        .map(|s| s.parse::<isize>().unwrap())
-        .map(get_recursive_fuel)
-        .sum::<isize>();
-
-    println!("Result: {:?}", result);
+    assert_eq!(Breakdown::new(1969_u128, &FuelModel::default(), Negative::Error).unwrap().total(), Some(966));
+
+    // `big` for masses past u128, `strict` to reject modules too light to need fuel,
+    // `breakdown` to show every stage of every module, `models=<file>` to
+    // compare the totals from every model in a config, `report=csv|json|table`
+    // for a row per module with totals and statistics, `skip` to carry on past
+    // bad lines, `threads=<n>` to total in parallel and `input=<file>` to read
+    // masses from somewhere other than data.text
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let negative = if args.iter().any(|a| a == "strict") { Negative::Error } else { Negative::Clamp };
+    let show = args.iter().any(|a| a == "breakdown");
+    let big = args.iter().any(|a| a == "big");
+    let bad_lines = if args.iter().any(|a| a == "skip") { BadLines::Skip } else { BadLines::Error };
+    let path = args.iter().find_map(|a| a.strip_prefix("input=")).unwrap_or("data.text");
+
+    let threads = match args.iter().find_map(|a| a.strip_prefix("threads=")) {
+        Some(n) => n.parse::<usize>().unwrap_or_else(|e| {
+            eprintln!("Invalid thread count {:?}: {}", n, e);
+            std::process::exit(1);
+        }),
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let report = args.iter().find_map(|a| a.strip_prefix("report=")).map(|f| {
+        f.parse::<Format>().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+
+    let models = match args.iter().find_map(|a| a.strip_prefix("models=")) {
+        Some(path) => {
+            let config = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read {}: {}", path, e));
+            FuelModel::parse_config(&config).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            })
+        }
+        None => vec![("standard".to_string(), FuelModel::default())],
+    };
+
+    let mut failed = false;
+
+    for (name, model) in models.iter() {
+        let stream = Stream::new(model, negative).bad_lines(bad_lines).threads(threads);
+        let result = if big {
+            summarize::<BigMass>(path, &stream, show, report)
+        } else {
+            summarize::<u128>(path, &stream, show, report)
+        };
+
+        match result {
+            Ok(result) if report.is_some() => {
+                if models.len() > 1 {
+                    println!("{} ({}):", name, model);
+                }
+                print!("{}", result);
+            }
+            Ok(result) if models.len() == 1 => println!("Result: {}", result),
+            Ok(result) => println!("{} ({}): {}", name, model, result),
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::fuel::Mass;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+    // Halves round up
+    Nearest,
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Rounding, String> {
+        match text {
+            "floor" => Ok(Rounding::Floor),
+            "ceil" => Ok(Rounding::Ceil),
+            "nearest" => Ok(Rounding::Nearest),
+            _ => Err(format!("unknown rounding {:?}, expected floor, ceil or nearest", text)),
+        }
+    }
+}
+
+// A rocket equation: round(mass / divisor) - subtract, optionally
+// with fuel for that fuel and so on
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuelModel {
+    pub divisor: u64,
+    pub subtract: u64,
+    pub rounding: Rounding,
+    pub recursive: bool,
+}
+
+impl Default for FuelModel {
+    // The day 1 part 2 equation
+    fn default() -> FuelModel {
+        FuelModel { divisor: 3, subtract: 2, rounding: Rounding::Floor, recursive: true }
+    }
+}
+
+impl fmt::Display for FuelModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}(mass / {}) - {}", self.rounding, self.divisor, self.subtract)?;
+        if self.recursive {
+            write!(f, ", recursive")?;
+        }
+        Ok(())
+    }
+}
+
+impl FuelModel {
+    // None if the fuel would be negative
+    pub fn fuel<M: Mass>(&self, mass: &M) -> Option<M> {
+        let (quotient, rem) = mass.div_rem(self.divisor);
+
+        let round_up = match self.rounding {
+            Rounding::Floor => false,
+            Rounding::Ceil => rem != 0,
+            Rounding::Nearest => rem * 2 >= self.divisor,
+        };
+
+        let rounded = if round_up { quotient.checked_add(&M::from_u64(1))? } else { quotient };
+
+        rounded.checked_sub(self.subtract)
+    }
+
+    // Named models from a config like:
+    //
+    //   # comments and blank lines are ignored
+    //   [standard]
+    //   divisor = 3
+    //   subtract = 2
+    //   rounding = floor
+    //   recursive = true
+    //
+    // Keys left out keep the standard model's values.
+    pub fn parse_config(text: &str) -> Result<Vec<(String, FuelModel)>, String> {
+        let mut models: Vec<(String, FuelModel)> = Vec::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let fail = |message: String| format!("line {}: {}", idx + 1, message);
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                models.push((name.trim().to_string(), FuelModel::default()));
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+                None => return Err(fail(format!("expected `key = value`, got {:?}", line))),
+            };
+
+            let model = match models.last_mut() {
+                Some((_, model)) => model,
+                None => return Err(fail("settings must come after a [name]".to_string())),
+            };
+
+            let number = || value.parse::<u64>().map_err(|_| fail(format!("invalid number {:?}", value)));
+
+            match key {
+                "divisor" => match number()? {
+                    0 => return Err(fail("divisor can't be 0".to_string())),
+                    divisor => model.divisor = divisor,
+                },
+                "subtract" => model.subtract = number()?,
+                "rounding" => model.rounding = value.parse().map_err(fail)?,
+                "recursive" => model.recursive = value.parse().map_err(|_| fail(format!("expected true or false, got {:?}", value)))?,
+                _ => return Err(fail(format!("unknown setting {:?}", key))),
+            }
+        }
+
+        Ok(models)
+    }
+}
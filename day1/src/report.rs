@@ -0,0 +1,187 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::fuel::{FuelError, Mass};
+use crate::stream::Module;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Format, String> {
+        match text {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "table" => Ok(Format::Table),
+            _ => Err(format!("Unknown report format {:?}, expected csv, json or table", text)),
+        }
+    }
+}
+
+const COLUMNS: [&str; 4] = ["mass", "direct_fuel", "recursive_fuel", "depth"];
+
+// Every value as text, since masses can be any size and means aren't whole
+type Row = [String; 4];
+
+// Exact decimal for `sum / count`, to two places, rounded down. None if there's nothing to average.
+pub fn mean<M: Mass>(sum: &M, count: u64) -> Option<String> {
+    if count == 0 {
+        return None;
+    }
+
+    let (whole, rem) = sum.div_rem(count);
+    Some(format!("{}.{:02}", whole, rem as u128 * 100 / count as u128))
+}
+
+// None if there are no values, or the middle two overflow when added
+pub fn median<M: Mass>(sorted: &[M]) -> Option<String> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let mid = sorted.len() / 2;
+
+    if sorted.len() % 2 == 1 {
+        return Some(sorted[mid].to_string());
+    }
+
+    let (half, rem) = sorted[mid - 1].checked_add(&sorted[mid])?.div_rem(2);
+    Some(if rem == 0 { half.to_string() } else { format!("{}.5", half) })
+}
+
+// total, min, max, mean and median of one column, in that order, or None for an empty column
+pub fn column_stats<M: Mass>(mut values: Vec<M>) -> Result<Option<[String; 5]>, FuelError> {
+    let total = values.iter().try_fold(M::zero(), |t, v| t.checked_add(v)).ok_or(FuelError::Overflow)?;
+    values.sort();
+
+    let (min, max, mean) = match (values.first(), values.last(), mean(&total, values.len() as u64)) {
+        (Some(min), Some(max), Some(mean)) => (min.to_string(), max.to_string(), mean),
+        _ => return Ok(None),
+    };
+
+    Ok(Some([total.to_string(), min, max, mean, median(&values).ok_or(FuelError::Overflow)?]))
+}
+
+pub struct Report {
+    // (line in the input, row) for each module
+    modules: Vec<(usize, Row)>,
+    // (name, row) for total, min, max, mean and median
+    stats: Vec<(&'static str, Row)>,
+}
+
+impl Report {
+    pub fn new<M: Mass>(breakdowns: &[Module<M>]) -> Result<Report, FuelError> {
+        let mut modules = Vec::new();
+        let mut columns: Vec<Vec<M>> = vec![Vec::new(); 4];
+
+        for (line, breakdown) in breakdowns.iter() {
+            let values = [
+                breakdown.mass.clone(),
+                breakdown.direct(),
+                breakdown.total().ok_or(FuelError::Overflow)?,
+                M::from_u64(breakdown.depth() as u64),
+            ];
+            modules.push((*line, [values[0].to_string(), values[1].to_string(), values[2].to_string(), values[3].to_string()]));
+
+            for (column, value) in columns.iter_mut().zip(values.iter()) {
+                column.push(value.clone());
+            }
+        }
+
+        let mut stats = Vec::new();
+
+        let per_column = columns.into_iter().map(column_stats).collect::<Result<Vec<_>, _>>()?;
+
+        // Every column has a value per module, so with no modules there are no statistics
+        if let Some(per_column) = per_column.into_iter().collect::<Option<Vec<_>>>() {
+            for (idx, name) in ["total", "min", "max", "mean", "median"].iter().enumerate() {
+                let row = [per_column[0][idx].clone(), per_column[1][idx].clone(), per_column[2][idx].clone(), per_column[3][idx].clone()];
+                stats.push((*name, row));
+            }
+        }
+
+        Ok(Report { modules, stats })
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Csv => self.csv(),
+            Format::Json => self.json(),
+            Format::Table => self.table(),
+        }
+    }
+
+    // Modules are labelled with their line in the input, and statistics are
+    // labelled rows after them
+    fn csv(&self) -> String {
+        let mut result = format!("line,{}\n", COLUMNS.join(","));
+
+        for (line, row) in self.modules.iter() {
+            writeln!(result, "{},{}", line, row.join(",")).unwrap();
+        }
+        for (name, row) in self.stats.iter() {
+            writeln!(result, "{},{}", name, row.join(",")).unwrap();
+        }
+
+        result
+    }
+
+    fn json(&self) -> String {
+        // Every value is a plain number, so they can go in unquoted
+        let object = |line: Option<usize>, row: &Row| {
+            let fields = line.map(|l| format!(r#""line":{}"#, l)).into_iter()
+                .chain(COLUMNS.iter().zip(row.iter()).map(|(c, v)| format!(r#""{}":{}"#, c, v)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(","))
+        };
+
+        let mut result = String::from("{\"modules\":[");
+        let modules = self.modules.iter().map(|(line, row)| format!("\n  {}", object(Some(*line), row))).collect::<Vec<_>>();
+        result += &modules.join(",");
+        result += "\n]";
+
+        for (name, row) in self.stats.iter() {
+            write!(result, ",\n\"{}\":{}", name, object(None, row)).unwrap();
+        }
+        result += "\n}\n";
+
+        result
+    }
+
+    fn table(&self) -> String {
+        let rows = self.modules.iter().map(|(line, row)| (line.to_string(), row))
+            .chain(self.stats.iter().map(|(name, row)| (name.to_string(), row)))
+            .collect::<Vec<_>>();
+
+        let mut widths = [4, COLUMNS[0].len(), COLUMNS[1].len(), COLUMNS[2].len(), COLUMNS[3].len()];
+        for (label, row) in rows.iter() {
+            widths[0] = widths[0].max(label.len());
+            for (width, value) in widths[1..].iter_mut().zip(row.iter()) {
+                *width = (*width).max(value.len());
+            }
+        }
+
+        let line = |label: &str, values: &[&str]| {
+            let cells = values.iter().zip(widths[1..].iter()).map(|(v, w)| format!("{:>w$}", v, w = w)).collect::<Vec<_>>();
+            format!("{:<w$}  {}\n", label, cells.join("  "), w = widths[0])
+        };
+
+        let rule = format!("{}\n", "-".repeat(widths.iter().sum::<usize>() + 2 * COLUMNS.len()));
+        let mut result = line("line", &COLUMNS) + &rule;
+
+        for (idx, (label, row)) in rows.iter().enumerate() {
+            if idx == self.modules.len() {
+                result += &rule;
+            }
+            result += &line(label, &row.iter().map(|v| v.as_str()).collect::<Vec<_>>());
+        }
+
+        result
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt::{self, Display};
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::fuel::{Breakdown, FuelError, Mass, Negative};
+use crate::model::FuelModel;
+
+// What to do with a line that isn't a usable mass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BadLines {
+    Error,
+    Skip,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadLine {
+    // Counting from 1
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+impl Display for BadLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {:?}: {}", self.line, self.text, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    BadLine(BadLine),
+    Fuel(FuelError),
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::Io(e) => write!(f, "could not read masses: {}", e),
+            StreamError::BadLine(bad) => bad.fmt(f),
+            StreamError::Fuel(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> StreamError {
+        StreamError::Io(e)
+    }
+}
+
+// A module's line number, counting from 1, and its breakdown
+pub type Module<M> = (usize, Breakdown<M>);
+
+pub struct Summary<M> {
+    pub total: M,
+    // Only ever filled in when skipping bad lines
+    pub skipped: Vec<BadLine>,
+}
+
+// Up to `max` lines, without their line endings
+fn read_chunk<R: BufRead>(reader: &mut R, max: usize) -> io::Result<Vec<Vec<u8>>> {
+    let mut lines = Vec::new();
+
+    while lines.len() < max {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        lines.push(line);
+    }
+
+    Ok(lines)
+}
+
+// Reads masses a line at a time, so lists far bigger than memory are fine
+pub struct Stream<'a> {
+    model: &'a FuelModel,
+    negative: Negative,
+    bad_lines: BadLines,
+    threads: usize,
+    chunk_lines: usize,
+}
+
+impl<'a> Stream<'a> {
+    pub fn new(model: &'a FuelModel, negative: Negative) -> Stream<'a> {
+        Stream { model, negative, bad_lines: BadLines::Error, threads: 1, chunk_lines: 1 << 16 }
+    }
+
+    pub fn bad_lines(mut self, bad_lines: BadLines) -> Stream<'a> {
+        self.bad_lines = bad_lines;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Stream<'a> {
+        self.threads = threads.max(1);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn chunk_lines(mut self, chunk_lines: usize) -> Stream<'a> {
+        self.chunk_lines = chunk_lines.max(1);
+        self
+    }
+
+    // The breakdown for one line, or None if it's blank
+    fn module<M>(&self, line: usize, raw: &[u8]) -> Result<Option<Breakdown<M>>, BadLine>
+        where M: Mass + FromStr, M::Err: Display
+    {
+        let bad = |message: String| BadLine { line, text: String::from_utf8_lossy(raw).into_owned(), message };
+
+        let text = std::str::from_utf8(raw).map_err(|_| bad("not valid UTF-8".to_string()))?.trim();
+        if text.is_empty() {
+            return Ok(None);
+        }
+
+        let mass = text.parse::<M>().map_err(|e| bad(e.to_string()))?;
+        Breakdown::new(mass, self.model, self.negative).map(Some).map_err(|e| bad(e.to_string()))
+    }
+
+    // Every module in `lines`, the first of which is line `first`, passing
+    // each to `visit` with its line number and collecting the bad lines that
+    // were skipped
+    fn each<M, F>(&self, first: usize, lines: &[Vec<u8>], mut visit: F) -> Result<Vec<BadLine>, StreamError>
+        where M: Mass + FromStr, M::Err: Display, F: FnMut(usize, Breakdown<M>) -> Result<(), StreamError>
+    {
+        let mut skipped = Vec::new();
+
+        for (idx, raw) in lines.iter().enumerate() {
+            match (self.module::<M>(first + idx, raw), self.bad_lines) {
+                (Ok(Some(breakdown)), _) => visit(first + idx, breakdown)?,
+                (Ok(None), _) => (),
+                (Err(bad), BadLines::Skip) => skipped.push(bad),
+                (Err(bad), BadLines::Error) => return Err(StreamError::BadLine(bad)),
+            }
+        }
+
+        Ok(skipped)
+    }
+
+    fn chunk_total<M>(&self, first: usize, lines: &[Vec<u8>]) -> Result<Summary<M>, StreamError>
+        where M: Mass + FromStr, M::Err: Display
+    {
+        let mut total = M::zero();
+
+        let skipped = self.each(first, lines, |_, breakdown: Breakdown<M>| {
+            let fuel = breakdown.total().ok_or(StreamError::Fuel(FuelError::Overflow))?;
+            total = total.checked_add(&fuel).ok_or(StreamError::Fuel(FuelError::Overflow))?;
+            Ok(())
+        })?;
+
+        Ok(Summary { total, skipped })
+    }
+
+    // The total fuel for every mass in `reader`. Reads a chunk per thread at a
+    // time and totals them in parallel, so a bad line that stops the run is
+    // always the first one in the file.
+    pub fn total<M, R>(&self, mut reader: R) -> Result<Summary<M>, StreamError>
+        where M: Mass + FromStr + Send, M::Err: Display, R: BufRead
+    {
+        let mut summary = Summary { total: M::zero(), skipped: Vec::new() };
+        let mut line = 1;
+
+        loop {
+            let mut chunks = Vec::with_capacity(self.threads);
+            for _ in 0..self.threads {
+                let chunk = read_chunk(&mut reader, self.chunk_lines)?;
+                if chunk.is_empty() {
+                    break;
+                }
+                let first = line;
+                line += chunk.len();
+                chunks.push((first, chunk));
+            }
+
+            if chunks.is_empty() {
+                return Ok(summary);
+            }
+
+            let results = std::thread::scope(|scope| {
+                let handles = chunks.iter()
+                    .map(|(first, lines)| scope.spawn(move || self.chunk_total::<M>(*first, lines)))
+                    .collect::<Vec<_>>();
+                handles.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>()
+            });
+
+            for result in results {
+                let chunk = result?;
+                summary.total = summary.total.checked_add(&chunk.total).ok_or(StreamError::Fuel(FuelError::Overflow))?;
+                summary.skipped.extend(chunk.skipped);
+            }
+        }
+    }
+
+    // Every module's line number and breakdown, for when the whole list is
+    // needed at once
+    pub fn breakdowns<M, R>(&self, mut reader: R) -> Result<(Vec<Module<M>>, Vec<BadLine>), StreamError>
+        where M: Mass + FromStr, M::Err: Display, R: BufRead
+    {
+        let mut breakdowns = Vec::new();
+        let mut skipped = Vec::new();
+        let mut line = 1;
+
+        loop {
+            let chunk = read_chunk(&mut reader, self.chunk_lines)?;
+            if chunk.is_empty() {
+                return Ok((breakdowns, skipped));
+            }
+
+            skipped.extend(self.each(line, &chunk, |line, breakdown| {
+                breakdowns.push((line, breakdown));
+                Ok(())
+            })?);
+            line += chunk.len();
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use super::*;
+use stream::StreamError;
+
+fn big(value: u128) -> BigMass {
+    value.to_string().parse().unwrap()
+}
+
+// Values either side of each limb boundary, plus a few with long runs of
+// zero digits to check the nine-digit chunks are padded
+fn edges() -> Vec<u128> {
+    let mut values = vec![0, 1, 2, 999_999_999, 1_000_000_000, 1_000_000_001, 1_000_000_000_000_000_000];
+    for shift in [32, 64, 96].iter() {
+        let edge = 1u128 << shift;
+        values.extend(vec![edge - 1, edge, edge + 1]);
+    }
+    values.push(u128::MAX / 2);
+    values
+}
+
+#[test]
+fn big_mass_round_trips() {
+    for value in edges() {
+        assert_eq!(big(value).to_string(), value.to_string());
+    }
+
+    let huge = "1000000000000000000000000000000000000000000000000000000000000000000000000000000000001";
+    assert_eq!(huge.parse::<BigMass>().unwrap().to_string(), huge);
+    assert_eq!("0000".parse::<BigMass>().unwrap().to_string(), "0");
+
+    for bad in ["", "-1", "12a", " 3"].iter() {
+        assert_eq!(bad.parse::<BigMass>(), Err(format!("Invalid mass {:?}", bad)));
+    }
+}
+
+#[test]
+fn big_mass_arithmetic() {
+    let values = edges();
+
+    for &a in values.iter() {
+        for &b in values.iter() {
+            assert_eq!(big(a).add(&big(b)), big(a + b), "{} + {}", a, b);
+            assert_eq!(big(a).cmp(&big(b)), a.cmp(&b), "{} cmp {}", a, b);
+        }
+
+        for &amount in [0, 1, u32::MAX as u64, 1 << 32, u64::MAX].iter() {
+            assert_eq!(big(a).checked_sub(amount), a.checked_sub(amount as u128).map(big), "{} - {}", a, amount);
+        }
+
+        for &divisor in [1, 3, 7, 1_000_000_000, 1 << 32, u64::MAX].iter() {
+            let (quotient, rem) = big(a).div_rem(divisor);
+            assert_eq!((quotient, rem as u128), (big(a / divisor as u128), a % divisor as u128), "{} / {}", a, divisor);
+        }
+    }
+}
+
+fn stages(mass: u128) -> Vec<u128> {
+    Breakdown::new(mass, &FuelModel::default(), Negative::Error).unwrap().stages
+}
+
+#[test]
+fn breakdown_stages() {
+    assert_eq!(stages(14), vec![2]);
+    assert_eq!(stages(1969), vec![654, 216, 70, 21, 5]);
+
+    let breakdown = Breakdown::new(100756u128, &FuelModel::default(), Negative::Error).unwrap();
+    assert_eq!(breakdown.stages, vec![33583, 11192, 3728, 1240, 411, 135, 43, 12, 2]);
+    assert_eq!((breakdown.direct(), breakdown.total(), breakdown.depth()), (33583, Some(50346), 9));
+
+    // Big masses give the same stages
+    let breakdown = Breakdown::new(big(1969), &FuelModel::default(), Negative::Error).unwrap();
+    assert_eq!(breakdown.stages, vec![big(654), big(216), big(70), big(21), big(5)]);
+}
+
+#[test]
+fn negative_fuel() {
+    let clamped = Breakdown::new(5u128, &FuelModel::default(), Negative::Clamp).unwrap();
+    assert_eq!(clamped.stages, Vec::<u128>::new());
+    assert_eq!((clamped.direct(), clamped.total(), clamped.depth()), (0, Some(0), 0));
+
+    assert_eq!(Breakdown::new(5u128, &FuelModel::default(), Negative::Error), Err(FuelError::Negative("5".to_string())));
+
+    // Exactly zero fuel isn't negative
+    assert_eq!(Breakdown::new(6u128, &FuelModel::default(), Negative::Error).unwrap().stages, Vec::<u128>::new());
+}
+
+#[test]
+fn config_models() {
+    let config = "
+        # two models
+        [standard]
+
+        [rough]   # ceil, no fuel for fuel
+        divisor = 4
+        subtract = 1
+        rounding = ceil
+        recursive = false
+    ";
+
+    let models = FuelModel::parse_config(config).unwrap();
+    assert_eq!(models, vec![
+        ("standard".to_string(), FuelModel::default()),
+        ("rough".to_string(), FuelModel { divisor: 4, subtract: 1, rounding: model::Rounding::Ceil, recursive: false }),
+    ]);
+}
+
+#[test]
+fn config_errors() {
+    let cases = vec![
+        ("divisor = 3", "line 1: settings must come after a [name]"),
+        ("[a]\n\ndivisor 3", r#"line 3: expected `key = value`, got "divisor 3""#),
+        ("[a]\n# zero\ndivisor = 0", "line 3: divisor can't be 0"),
+        ("[a]\nsubtract = -2", r#"line 2: invalid number "-2""#),
+        ("[a]\nrounding = up", r#"line 2: unknown rounding "up", expected floor, ceil or nearest"#),
+        ("[a]\n[b]\nrecursive = yes", r#"line 3: expected true or false, got "yes""#),
+        ("[a]\nweight = 1", r#"line 2: unknown setting "weight""#),
+    ];
+
+    for (config, message) in cases {
+        assert_eq!(FuelModel::parse_config(config), Err(message.to_string()), "{:?}", config);
+    }
+}
+
+#[test]
+fn rounding() {
+    let model = |rounding| FuelModel { divisor: 4, subtract: 0, rounding, recursive: false };
+
+    for &(mass, floor, ceil, nearest) in [(8u128, 2, 2, 2), (9, 2, 3, 2), (10, 2, 3, 3), (11, 2, 3, 3)].iter() {
+        assert_eq!(model(model::Rounding::Floor).fuel(&mass), Some(floor));
+        assert_eq!(model(model::Rounding::Ceil).fuel(&mass), Some(ceil));
+        assert_eq!(model(model::Rounding::Nearest).fuel(&mass), Some(nearest));
+    }
+}
+
+#[test]
+fn endless_fuel() {
+    let endless = FuelModel { divisor: 1, subtract: 0, ..FuelModel::default() };
+    assert_eq!(Breakdown::new(12u128, &endless, Negative::Error), Err(FuelError::Endless("12".to_string())));
+    assert_eq!(Breakdown::new(big(12), &endless, Negative::Error), Err(FuelError::Endless("12".to_string())));
+
+    // Rounding up can stop the fuel shrinking too, once it gets down to 1
+    let ceil = FuelModel { divisor: 2, subtract: 0, rounding: model::Rounding::Ceil, recursive: true };
+    assert_eq!(Breakdown::new(12u128, &ceil, Negative::Error), Err(FuelError::Endless("12".to_string())));
+
+    // Without fuel for fuel there's only ever one stage
+    let once = FuelModel { recursive: false, ..endless };
+    assert_eq!(Breakdown::new(12u128, &once, Negative::Error).unwrap().stages, vec![12]);
+
+    // No fuel at all isn't endless
+    assert_eq!(Breakdown::new(0u128, &endless, Negative::Error).unwrap().depth(), 0);
+}
+
+#[test]
+fn report_rows_keep_line_numbers() {
+    let model = FuelModel::default();
+    let stream = Stream::new(&model, Negative::Clamp).bad_lines(BadLines::Skip);
+    let (breakdowns, skipped) = stream.breakdowns::<u128, _>(&b"12\n\nabc\n1969\n5\n100756\n"[..]).unwrap();
+
+    assert_eq!(skipped.iter().map(|bad| bad.line).collect::<Vec<_>>(), vec![3]);
+
+    let report = Report::new(&breakdowns).unwrap();
+    let csv = report.render(Format::Csv);
+    let rows = csv.lines().skip(1).take(4).map(|row| row.split(',').next().unwrap()).collect::<Vec<_>>();
+    assert_eq!(rows, vec!["1", "4", "5", "6"]);
+
+    assert!(report.render(Format::Json).contains(r#"{"line":4,"mass":1969,"direct_fuel":654,"recursive_fuel":966,"depth":5}"#));
+    assert!(report.render(Format::Table).lines().any(|row| row.starts_with("6 ") && row.ends_with(" 9")));
+}
+
+#[test]
+fn report_stats() {
+    // Odd counts take the middle value, even ones halve the middle two
+    assert_eq!(report::median(&[1u128, 3, 7]), Some("3".to_string()));
+    assert_eq!(report::median(&[1u128, 3, 4, 8]), Some("3.5".to_string()));
+    assert_eq!(report::median(&[2u128, 4]), Some("3".to_string()));
+    assert_eq!(report::median(&[big(5), big(u128::MAX / 2 + 1)]), Some(format!("{}.5", (1u128 << 126) + 2)));
+    assert_eq!(report::median(&[u128::MAX, u128::MAX]), None);
+
+    // Means are cut off at two places, not rounded
+    assert_eq!(report::mean(&10u128, 3), Some("3.33".to_string()));
+    assert_eq!(report::mean(&2u128, 3), Some("0.66".to_string()));
+    assert_eq!(report::mean(&big(1 << 100), 8), Some(format!("{}.00", 1u128 << 97)));
+
+    let stats = report::column_stats(vec![5u128, 1, 3, 8]).unwrap().unwrap();
+    assert_eq!(stats, ["17", "1", "8", "4.25", "4"].map(String::from));
+}
+
+#[test]
+fn report_without_modules() {
+    assert_eq!(report::median::<u128>(&[]), None);
+    assert_eq!(report::mean(&0u128, 0), None);
+    assert_eq!(report::column_stats::<u128>(Vec::new()).unwrap(), None);
+
+    // Just the headers, with no statistics rows
+    let report = Report::new::<u128>(&[]).unwrap();
+    assert_eq!(report.render(Format::Csv), "line,mass,direct_fuel,recursive_fuel,depth\n");
+    assert_eq!(report.render(Format::Json), "{\"modules\":[\n]\n}\n");
+    assert_eq!(report.render(Format::Table).lines().count(), 2);
+}
+
+// Masses 1 to 300 with a blank line every so often, and any bad lines given
+// put in place of the masses on those lines
+fn masses(bad: &[usize]) -> String {
+    (1..=300).map(|line| match line {
+        _ if bad.contains(&line) => format!("bad{}\n", line),
+        _ if line % 17 == 0 => "\n".to_string(),
+        _ => format!("{}\n", line * 1000),
+    }).collect()
+}
+
+#[test]
+fn parallel_totals() {
+    let model = FuelModel::default();
+    let text = masses(&[]);
+
+    let (breakdowns, _) = Stream::new(&model, Negative::Clamp).breakdowns::<u128, _>(text.as_bytes()).unwrap();
+    let expected = breakdowns.iter().map(|(_, b)| b.total().unwrap()).sum::<u128>();
+
+    for &threads in [1, 2, 3, 8].iter() {
+        for &chunk_lines in [1, 5, 16, 1000].iter() {
+            let stream = Stream::new(&model, Negative::Clamp).threads(threads).chunk_lines(chunk_lines);
+            assert_eq!(stream.total::<u128, _>(text.as_bytes()).unwrap().total, expected, "{} threads, {} lines", threads, chunk_lines);
+            assert_eq!(stream.total::<BigMass, _>(text.as_bytes()).unwrap().total, big(expected));
+        }
+    }
+}
+
+#[test]
+fn first_bad_line_stops_the_stream() {
+    let model = FuelModel::default();
+    // With 4 threads of 5 lines, 12 and 7 are in the same batch but different chunks
+    let text = masses(&[7, 12, 250]);
+
+    for &threads in [1, 4].iter() {
+        let stream = Stream::new(&model, Negative::Clamp).threads(threads).chunk_lines(5);
+
+        match stream.total::<u128, _>(text.as_bytes()) {
+            Err(StreamError::BadLine(bad)) => {
+                assert_eq!(bad.to_string(), r#"line 7: "bad7": invalid digit found in string"#);
+            }
+            other => panic!("Expected a bad line, got {:?}", other.map(|s| s.total)),
+        }
+
+        match stream.breakdowns::<u128, _>(text.as_bytes()) {
+            Err(StreamError::BadLine(bad)) => assert_eq!(bad.line, 7),
+            other => panic!("Expected a bad line, got {:?}", other.map(|(b, _)| b.len())),
+        }
+    }
+}
+
+#[test]
+fn skipped_lines_in_order() {
+    let model = FuelModel::default();
+    let bad = [3, 4, 18, 99, 100, 299];
+    let text = masses(&bad);
+
+    let stream = Stream::new(&model, Negative::Clamp).bad_lines(BadLines::Skip);
+    let (breakdowns, skipped) = stream.breakdowns::<u128, _>(text.as_bytes()).unwrap();
+    assert_eq!(skipped.iter().map(|b| b.line).collect::<Vec<_>>(), bad.to_vec());
+    let expected = breakdowns.iter().map(|(_, b)| b.total().unwrap()).sum::<u128>();
+
+    for &threads in [1, 3, 8].iter() {
+        let stream = Stream::new(&model, Negative::Clamp).bad_lines(BadLines::Skip).threads(threads).chunk_lines(4);
+        let summary = stream.total::<u128, _>(text.as_bytes()).unwrap();
+        assert_eq!(summary.skipped, skipped);
+        assert_eq!(summary.total, expected);
+    }
+}
@@ -1,141 +1,157 @@
-use std::collections::HashMap;
-
-#[derive(Debug, Clone, Copy)]
+mod parse;
+mod rank;
+mod render;
+mod segment;
+#[cfg(test)]
+mod tests;
+
+use parse::ErrorKind;
+use rank::Metric;
+use render::Scene;
+use segment::Options;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Direction {
     Right,
     Down,
     Left,
-    Up
-}
-
-impl Direction {
-    pub fn parse(c: char) -> Direction {
-        match c {
-            'R' => Direction::Right,
-            'D' => Direction::Down,
-            'L' => Direction::Left,
-            'U' => Direction::Up,
-            _ => panic!("Invalid {:?}", c)
-        }
-    }
+    Up,
+    UpRight,
+    UpLeft,
+    DownRight,
+    DownLeft,
+    Forward,
+    Back,
 }
 
-fn parse_one(d: &str) -> (Direction, usize) {
-    (Direction::parse(d.chars().next().unwrap()), d[1..].parse::<usize>().unwrap())
+// Which moves a wire may use. Each allows everything the one before it does.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Moves {
+    // R, D, L, U
+    Four,
+    // Adds the diagonals NE, NW, SE, SW
+    Eight,
+    // Adds F and B, which move between layers
+    Space,
 }
 
-fn parse_line(l: &str) -> Vec<(Direction, usize)> {
-    l.split(',').map(parse_one).collect()
-}
+impl Direction {
+    pub fn parse(d: &str, moves: Moves) -> Result<Direction, ErrorKind> {
+        let (dir, needs) = match d {
+            "R" => (Direction::Right, Moves::Four),
+            "D" => (Direction::Down, Moves::Four),
+            "L" => (Direction::Left, Moves::Four),
+            "U" => (Direction::Up, Moves::Four),
+            "NE" => (Direction::UpRight, Moves::Eight),
+            "NW" => (Direction::UpLeft, Moves::Eight),
+            "SE" => (Direction::DownRight, Moves::Eight),
+            "SW" => (Direction::DownLeft, Moves::Eight),
+            "F" => (Direction::Forward, Moves::Space),
+            "B" => (Direction::Back, Moves::Space),
+            _ => return Err(ErrorKind::UnknownDirection(d.to_string())),
+        };
+
+        if needs > moves {
+            return Err(ErrorKind::NotAllowed(d.to_string(), moves));
+        }
 
-const ROW_CENTER: usize = 5000;
-const GRID_SIZE: usize = 10000;
-
-// Output is 1000x1000
-fn fill_grid(dirs: &[(Direction, usize)]) -> Vec<bool> {
-    let mut row = ROW_CENTER;
-    let mut col = 4000;
-    let mut result = Vec::new();
-    result.resize(GRID_SIZE * GRID_SIZE, false);
-
-    for (dir, amount) in dirs {
-        println!("Row: {}, dir: {:?}, amount: {}", row, dir, amount);
-        for _ in 0..*amount {
-            match dir {
-                Direction::Up => row -= 1,
-                Direction::Down => row += 1,
-                Direction::Left => col -= 1,
-                Direction::Right => col += 1,
-            }
+        Ok(dir)
+    }
 
-            result[row * GRID_SIZE + col] = true;
+    // The change in (row, col, layer) for one step
+    pub fn delta(self) -> (isize, isize, isize) {
+        match self {
+            Direction::Right => (0, 1, 0),
+            Direction::Down => (1, 0, 0),
+            Direction::Left => (0, -1, 0),
+            Direction::Up => (-1, 0, 0),
+            Direction::UpRight => (-1, 1, 0),
+            Direction::UpLeft => (-1, -1, 0),
+            Direction::DownRight => (1, 1, 0),
+            Direction::DownLeft => (1, -1, 0),
+            Direction::Forward => (0, 0, 1),
+            Direction::Back => (0, 0, -1),
         }
     }
-
-    result
 }
 
-fn make_positions(dirs: &[(Direction, usize)]) -> Vec<((isize, isize), usize)> {
-    let mut row = 0;
-    let mut col = 0;
-    let mut count = 1;
-    let mut result = Vec::new();
-
-    for (dir, amount) in dirs {
-        println!("Row: {}, dir: {:?}, amount: {}", row, dir, amount);
-        for _ in 0..*amount {
-            match dir {
-                Direction::Up => row -= 1,
-                Direction::Down => row += 1,
-                Direction::Left => col -= 1,
-                Direction::Right => col += 1,
+fn main() {
+    // Optional arguments, in any order: how many wires must cross, `self` to count
+    // self-crossings, `ascii` to draw the wires here, .svg or .png files to draw them to,
+    // `metric=<name>` and `from=<row>,<col>[,<layer>]` to choose how the closest crossing
+    // is found, `moves=4`, `moves=8` or `moves=3d` to choose which moves wires can make,
+    // and `limit=<n>` to change how many overlapping points are allowed
+    let mut moves = Moves::Four;
+    let mut options = Options::default();
+    let mut ascii = false;
+    let mut images = Vec::new();
+    let mut metric = Metric::Manhattan;
+    let mut reference = (0, 0, 0);
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "self" => options.self_crossings = true,
+            "ascii" => ascii = true,
+            _ if arg.starts_with("metric=") => metric = arg["metric=".len()..].parse().unwrap_or_else(|e| panic!("{}", e)),
+            _ if arg.starts_with("from=") => {
+                let coords = arg["from=".len()..].split(',').map(|n| n.parse::<isize>().ok()).collect::<Vec<_>>();
+                match coords[..] {
+                    [Some(row), Some(col)] => reference = (row, col, 0),
+                    [Some(row), Some(col), Some(layer)] => reference = (row, col, layer),
+                    _ => panic!("Expected from=<row>,<col>[,<layer>], got {}", arg),
+                }
             }
-
-            result.push(((row, col), count));
-            
-            count += 1;
+            _ if arg.starts_with("limit=") => options.max_points = arg["limit=".len()..].parse().unwrap_or_else(|_| panic!("Expected limit=<points>, got {}", arg)),
+            "moves=4" => moves = Moves::Four,
+            "moves=8" => moves = Moves::Eight,
+            "moves=3d" => moves = Moves::Space,
+            path if path.ends_with(".svg") || path.ends_with(".png") => images.push(arg.clone()),
+            count => options.min_wires = count.parse::<usize>().expect("Expected a wire count, self, ascii or an image path"),
         }
     }
 
-    result
-}
-
-fn find_ints(dirs_l: &[(Direction, usize)], dirs_r: &[(Direction, usize)]) -> Vec<((isize, isize), usize)> {
-    let pos_l = make_positions(dirs_l);
-    let pos_r = make_positions(dirs_r);
-    println!("Len: {}, {}", pos_l.len(), pos_r.len());
-
-    let found = pos_l.into_iter().collect::<HashMap<(isize, isize), usize>>();
+    let inputs = match parse::parse_wires(&std::fs::read_to_string("./data.txt").unwrap(), moves) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("data.txt: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    pos_r
-        .into_iter()
-        .filter_map(|(pos, delay)| {
-            let delay2 = found.get(&pos)?;
-            Some((pos, delay + delay2))
-        })
-        .collect::<Vec<_>>()
-}
+    println!("Input: {:?}", inputs);
 
-fn common(g1: &[bool], g2: &[bool]) -> Vec<(usize, usize)> {
-    g1.iter().enumerate().zip(g2).filter_map(|((idx, e1), e2)| {
-        if *e1 && *e2 {
-            Some(idx)
-        } else {
-            None
+    let wires = inputs.iter().map(|dirs| segment::segments(dirs)).collect::<Vec<_>>();
+    let ints = match segment::intersect(&wires, &options) {
+        Ok(ints) => ints,
+        Err(e) => {
+            eprintln!("{}, try a bigger limit=", e);
+            std::process::exit(1);
         }
-    }).map(|idx| (idx / GRID_SIZE, idx % GRID_SIZE)).collect()
-}
+    };
 
-fn abs_diff(lhs: usize, rhs: usize) -> usize {
-    if lhs < rhs {
-        rhs - lhs
-    } else {
-        lhs - rhs
+    let scene = Scene::new(&wires, &ints);
+    if ascii {
+        print!("{}", scene.ascii(120, 50));
+    }
+    for path in images.iter() {
+        let data = if path.ends_with(".svg") { scene.svg().into_bytes() } else { scene.png(1000) };
+        std::fs::write(path, data).unwrap_or_else(|e| panic!("Could not write {}: {}", path, e));
     }
-}
-
-fn center_dist(r: usize, c: usize) -> usize {
-    abs_diff(r, ROW_CENTER) + abs_diff(c, 4000)
-}
 
-fn min_dist(inters: &[(isize, isize)]) -> ((isize, isize), isize) {
-    inters.iter().map(|(r, c)| ((*r, *c), r.abs() + c.abs())).min_by_key(|(_, d)| *d).unwrap()
-}
+    if ints.is_empty() {
+        println!("No crossings");
+        return;
+    }
 
-fn main() {
-    let inputs = std::fs::read_to_string("./data.txt")
-        .unwrap()
-        .lines()
-        .map(parse_line)
-        .collect::<Vec<_>>();
+    println!("Ints: {:?}", ints);
 
-    println!("Input: {:?}", inputs);
+    let ranked = rank::rank(&ints, &metric, reference);
 
-    let ints = find_ints(&inputs[0], &inputs[1]);
+    println!("Ranked: {:?}", ranked.iter().take(10).map(|(c, d)| (c.pos, *d)).collect::<Vec<_>>());
 
-    println!("Ints: {:?}", ints);
+    println!("Closest: {:?}", ranked[0]);
 
-    let min = ints.iter().min_by_key(|(pos, delay)| *delay).unwrap();
+    let min = rank::rank(&ints, &Metric::Delay, reference)[0];
 
     println!("Min: {:?}", min);
 }
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::num::IntErrorKind;
+
+use crate::{Direction, Moves};
+
+// The furthest any wire may get from the origin along each axis, so the
+// distance between any two points on any wires still fits in an isize
+pub const MAX_COORD: isize = isize::MAX / 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    // Nothing between two commas
+    EmptyMove,
+    UnknownDirection(String),
+    // A real direction, but not one the chosen moves allow
+    NotAllowed(String, Moves),
+    MissingLength,
+    BadLength(String),
+    // A length too big to fit
+    Overflow(String),
+    // The moves so far add up to more than a wire can walk
+    WireTooLong,
+    // The move takes the wire further from the origin than MAX_COORD
+    OutOfBounds,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::EmptyMove => write!(f, "empty move"),
+            ErrorKind::UnknownDirection(d) => write!(f, "unknown direction {:?}", d),
+            ErrorKind::NotAllowed(d, moves) => write!(f, "direction {:?} isn't allowed with {:?} moves", d, moves),
+            ErrorKind::MissingLength => write!(f, "missing length"),
+            ErrorKind::BadLength(l) => write!(f, "invalid length {:?}", l),
+            ErrorKind::Overflow(l) => write!(f, "length {} is too long", l),
+            ErrorKind::WireTooLong => write!(f, "wire is too long"),
+            ErrorKind::OutOfBounds => write!(f, "wire goes further than {} from the origin", MAX_COORD),
+        }
+    }
+}
+
+// Where parsing failed, counting wires and moves from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub wire: usize,
+    pub token: usize,
+    pub text: String,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wire {}, move {} ({:?}): {}", self.wire, self.token, self.text, self.kind)
+    }
+}
+
+pub fn parse_one(d: &str, moves: Moves) -> Result<(Direction, usize), ErrorKind> {
+    if d.is_empty() {
+        return Err(ErrorKind::EmptyMove);
+    }
+
+    let split = d.find(|c: char| c.is_ascii_digit()).unwrap_or(d.len());
+    let (dir, len) = (&d[..split], &d[split..]);
+
+    let dir = Direction::parse(dir, moves)?;
+
+    if len.is_empty() {
+        return Err(ErrorKind::MissingLength);
+    }
+
+    let len = len.parse::<usize>().map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow => ErrorKind::Overflow(len.to_string()),
+        _ => ErrorKind::BadLength(len.to_string()),
+    })?;
+
+    Ok((dir, len))
+}
+
+// One wire, allowing a trailing comma. Errors come with the move they're in, from 0.
+pub fn parse_line(l: &str, moves: Moves) -> Result<Vec<(Direction, usize)>, (usize, String, ErrorKind)> {
+    let l = l.trim();
+    let tokens = l.strip_suffix(',').unwrap_or(l).split(',').map(|t| t.trim());
+
+    let mut total = 0_usize;
+    let mut pos = [0_isize; 3];
+    let mut result = Vec::new();
+
+    for (idx, token) in tokens.enumerate() {
+        let fail = |kind| (idx, token.to_string(), kind);
+
+        let (dir, len) = parse_one(token, moves).map_err(fail)?;
+
+        // Points are isize, so no wire can walk further than that
+        total = total.checked_add(len).filter(|&t| t <= isize::MAX as usize).ok_or_else(|| fail(ErrorKind::WireTooLong))?;
+
+        let (dr, dc, dl) = dir.delta();
+        for (coord, delta) in pos.iter_mut().zip([dr, dc, dl].iter()) {
+            *coord = (len as isize).checked_mul(*delta).and_then(|d| coord.checked_add(d))
+                .filter(|c| c.abs() <= MAX_COORD)
+                .ok_or_else(|| fail(ErrorKind::OutOfBounds))?;
+        }
+
+        result.push((dir, len));
+    }
+
+    Ok(result)
+}
+
+// One wire per line, skipping blank lines
+pub fn parse_wires(text: &str, moves: Moves) -> Result<Vec<Vec<(Direction, usize)>>, ParseError> {
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(wire, l)| parse_line(l, moves).map_err(|(token, text, kind)| ParseError { wire: wire + 1, token: token + 1, text, kind }))
+        .collect()
+}
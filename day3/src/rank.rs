@@ -0,0 +1,61 @@
+use std::str::FromStr;
+
+use crate::segment::{Crossing, Point};
+
+pub type MetricFn<'a> = Box<dyn Fn(&Crossing, Point) -> f64 + 'a>;
+
+// How far a crossing is from a reference point. Lower ranks first.
+pub enum Metric<'a> {
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+    // Total steps all wires take to reach the crossing, ignoring the reference point
+    Delay,
+    Custom(MetricFn<'a>),
+}
+
+impl<'a> Metric<'a> {
+    pub fn custom(f: impl Fn(&Crossing, Point) -> f64 + 'a) -> Metric<'a> {
+        Metric::Custom(Box::new(f))
+    }
+
+    pub fn measure(&self, crossing: &Crossing, reference: Point) -> f64 {
+        let distance = |a: isize, b: isize| (a - b).abs() as f64;
+        let (dr, dc, dl) = (distance(crossing.pos.0, reference.0), distance(crossing.pos.1, reference.1), distance(crossing.pos.2, reference.2));
+
+        match self {
+            Metric::Manhattan => dr + dc + dl,
+            Metric::Chebyshev => dr.max(dc).max(dl),
+            Metric::Euclidean => (dr * dr + dc * dc + dl * dl).sqrt(),
+            Metric::Delay => crossing.total_delay() as f64,
+            Metric::Custom(f) => f(crossing, reference),
+        }
+    }
+}
+
+impl FromStr for Metric<'static> {
+    type Err = String;
+
+    // A name, or several joined with `+` to add them up
+    fn from_str(text: &str) -> Result<Metric<'static>, String> {
+        if text.contains('+') {
+            let parts = text.split('+').map(|part| part.parse::<Metric>()).collect::<Result<Vec<_>, _>>()?;
+            return Ok(Metric::custom(move |crossing, reference| parts.iter().map(|m| m.measure(crossing, reference)).sum()));
+        }
+
+        match text {
+            "manhattan" => Ok(Metric::Manhattan),
+            "chebyshev" => Ok(Metric::Chebyshev),
+            "euclidean" => Ok(Metric::Euclidean),
+            "delay" => Ok(Metric::Delay),
+            _ => Err(format!("Unknown metric {:?}, expected manhattan, chebyshev, euclidean or delay", text)),
+        }
+    }
+}
+
+// Every crossing with its distance, nearest first. Ties keep their original order.
+pub fn rank<'c>(crossings: &'c [Crossing], metric: &Metric, reference: Point) -> Vec<(&'c Crossing, f64)> {
+    let mut result = crossings.iter().map(|c| (c, metric.measure(c, reference))).collect::<Vec<_>>();
+    result.sort_by(|a, b| a.1.total_cmp(&b.1));
+    result
+}
@@ -0,0 +1,250 @@
+use std::fmt::Write;
+
+use crate::rank::{rank, Metric};
+use crate::segment::{Crossing, Point, Segment};
+
+const PALETTE: [[u8; 3]; 8] = [
+    [0x20, 0x60, 0xc0],
+    [0xe0, 0x40, 0x20],
+    [0x30, 0xa0, 0x40],
+    [0x90, 0x40, 0xc0],
+    [0xd0, 0x90, 0x10],
+    [0x10, 0xa0, 0xa0],
+    [0xc0, 0x30, 0x80],
+    [0x60, 0x60, 0x60],
+];
+
+const BACKGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+const ORIGIN: [u8; 3] = [0x00, 0x00, 0x00];
+const CROSSING: [u8; 3] = [0x80, 0x80, 0x80];
+const CLOSEST: [u8; 3] = [0xff, 0x00, 0x00];
+const FASTEST: [u8; 3] = [0x00, 0xb0, 0x00];
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// Everything to draw, plus the crossings worth pointing out
+pub struct Scene<'a> {
+    wires: &'a [Vec<Segment>],
+    crossings: &'a [Crossing],
+    // Closest to the origin by Manhattan distance
+    closest: Option<Point>,
+    // Lowest combined delay
+    fastest: Option<Point>,
+    low: Point,
+    high: Point,
+}
+
+impl<'a> Scene<'a> {
+    pub fn new(wires: &'a [Vec<Segment>], crossings: &'a [Crossing]) -> Scene<'a> {
+        let points = wires.iter().flatten().flat_map(|s| vec![s.start, s.end()]).chain(std::iter::once((0, 0, 0)));
+        // Only rows and columns matter, since every layer is drawn in the same place
+        let (mut low, mut high) = ((0, 0, 0), (0, 0, 0));
+        for (row, col, _) in points {
+            low = (low.0.min(row), low.1.min(col), 0);
+            high = (high.0.max(row), high.1.max(col), 0);
+        }
+
+        Scene {
+            wires,
+            crossings,
+            closest: rank(crossings, &Metric::Manhattan, (0, 0, 0)).first().map(|(c, _)| c.pos),
+            fastest: rank(crossings, &Metric::Delay, (0, 0, 0)).first().map(|(c, _)| c.pos),
+            low,
+            high,
+        }
+    }
+
+    // Wires can reach both ends of isize, so spans and offsets are worked out in i128
+    fn rows(&self) -> i128 {
+        self.high.0 as i128 - self.low.0 as i128 + 1
+    }
+
+    fn cols(&self) -> i128 {
+        self.high.1 as i128 - self.low.1 as i128 + 1
+    }
+
+    // How far `p` is from the top left corner, in columns and rows
+    fn offset(&self, p: Point) -> (f64, f64) {
+        ((p.1 as i128 - self.low.1 as i128) as f64, (p.0 as i128 - self.low.0 as i128) as f64)
+    }
+
+    pub fn svg(&self) -> String {
+        // Scale so the longer side is about 1000 pixels, whatever the wires' size
+        let scale = 1000.0 / self.rows().max(self.cols()) as f64;
+        let stroke = 2.0 / scale;
+        let x = |p: Point| self.offset(p).0;
+        let y = |p: Point| self.offset(p).1;
+
+        let mut result = String::new();
+        writeln!(
+            result, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="-1 -1 {} {}">"#,
+            self.cols() as f64 * scale, self.rows() as f64 * scale, self.cols() + 1, self.rows() + 1,
+        ).unwrap();
+        writeln!(result, r#"<rect x="-1" y="-1" width="{}" height="{}" fill="{}"/>"#, self.cols() + 1, self.rows() + 1, hex(BACKGROUND)).unwrap();
+
+        for (idx, wire) in self.wires.iter().enumerate() {
+            let points = std::iter::once((0, 0, 0)).chain(wire.iter().map(|s| s.end()))
+                .map(|p| format!("{},{}", x(p), y(p)))
+                .collect::<Vec<_>>();
+            writeln!(
+                result, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{:.3}"><title>wire {}</title></polyline>"#,
+                points.join(" "), hex(PALETTE[idx % PALETTE.len()]), stroke, idx + 1,
+            ).unwrap();
+        }
+
+        let mut marker = |p: Point, size: f64, color: [u8; 3], title: String| {
+            writeln!(
+                result, r#"<circle cx="{}" cy="{}" r="{:.3}" fill="{}"><title>{}</title></circle>"#,
+                x(p), y(p), size * stroke, hex(color), title,
+            ).unwrap();
+        };
+
+        for crossing in self.crossings.iter() {
+            marker(crossing.pos, 2.0, CROSSING, format!("{:?}, delay {}", crossing.pos, crossing.total_delay()));
+        }
+        marker((0, 0, 0), 4.0, ORIGIN, "origin".to_string());
+        if let Some(p) = self.closest {
+            marker(p, 5.0, CLOSEST, format!("closest {:?}", p));
+        }
+        if let Some(p) = self.fastest {
+            marker(p, 5.0, FASTEST, format!("lowest delay {:?}", p));
+        }
+
+        writeln!(result, "</svg>").unwrap();
+
+        result
+    }
+
+    // Draws onto a grid of `width` by `height` cells, with `wire` painting the
+    // cells a wire passes through and `mark` painting the points of interest
+    fn raster<T: Clone>(&self, width: usize, height: usize, background: T, uniform: bool,
+                        mut wire: impl FnMut(&mut T, usize), mut mark: impl FnMut(&mut T, Mark)) -> Vec<T> {
+        let mut sx = (width - 1) as f64 / (self.cols() - 1).max(1) as f64;
+        let mut sy = (height - 1) as f64 / (self.rows() - 1).max(1) as f64;
+        if uniform {
+            sx = sx.min(sy);
+            sy = sx;
+        }
+
+        let cell = |p: Point| {
+            let (x, y) = self.offset(p);
+            ((x * sx).round() as usize, (y * sy).round() as usize)
+        };
+        let mut cells = vec![background; width * height];
+
+        for (idx, segments) in self.wires.iter().enumerate() {
+            for segment in segments.iter() {
+                let (from, to) = (cell(segment.start), cell(segment.end()));
+                let (dx, dy) = (to.0 as f64 - from.0 as f64, to.1 as f64 - from.1 as f64);
+                let count = dx.abs().max(dy.abs()) as usize;
+
+                // Layers are drawn on top of each other, looking down from above
+                for i in 0..=count {
+                    let t = if count == 0 { 0.0 } else { i as f64 / count as f64 };
+                    let (x, y) = ((from.0 as f64 + dx * t).round() as usize, (from.1 as f64 + dy * t).round() as usize);
+                    wire(&mut cells[y * width + x], idx);
+                }
+            }
+        }
+
+        let mut marks = self.crossings.iter().map(|c| (c.pos, Mark::Crossing)).collect::<Vec<_>>();
+        marks.push(((0, 0, 0), Mark::Origin));
+        marks.extend(self.closest.map(|p| (p, Mark::Closest)));
+        marks.extend(self.fastest.map(|p| (p, Mark::Fastest)));
+
+        for (p, kind) in marks {
+            let (x, y) = cell(p);
+            mark(&mut cells[y * width + x], kind);
+        }
+
+        cells
+    }
+
+    // An RGB image whose longer side is `size` pixels
+    pub fn png(&self, size: usize) -> Vec<u8> {
+        let longest = self.rows().max(self.cols()) as f64;
+        let width = ((self.cols() as f64 / longest * size as f64).round() as usize).max(1);
+        let height = ((self.rows() as f64 / longest * size as f64).round() as usize).max(1);
+
+        let pixels = self.raster(
+            width, height, BACKGROUND, true,
+            |pixel, wire| *pixel = PALETTE[wire % PALETTE.len()],
+            |pixel, mark| *pixel = mark.color(),
+        );
+
+        // Single pixels are hard to spot, so grow each marker into a small square,
+        // drawing the highlighted ones last so they stay on top
+        let mut image = pixels.clone();
+        for &(color, radius) in [(CROSSING, 1), (ORIGIN, 2), (CLOSEST, 3), (FASTEST, 3)].iter() {
+            for idx in (0..pixels.len()).filter(|&idx| pixels[idx] == color) {
+                let (x, y) = ((idx % width) as isize, (idx / width) as isize);
+                for ny in (y - radius).max(0)..(y + radius + 1).min(height as isize) {
+                    for nx in (x - radius).max(0)..(x + radius + 1).min(width as isize) {
+                        image[ny as usize * width + nx as usize] = color;
+                    }
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        let mut encoder = png::Encoder::new(&mut result, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data = image.iter().flatten().copied().collect::<Vec<_>>();
+        encoder.write_header().and_then(|mut writer| writer.write_image_data(&data)).expect("Could not encode PNG");
+
+        result
+    }
+
+    // Wires are numbered 1-9 then a-z, with `#` where several overlap.
+    // `o` is the origin, `*` a crossing, `C` the closest and `D` the lowest delay.
+    pub fn ascii(&self, width: usize, height: usize) -> String {
+        const WIRES: &[u8] = b"123456789abcdefghijklmnopqrstuvwxyz";
+
+        let cells = self.raster(
+            width, height, ' ', false,
+            |cell, wire| {
+                let symbol = WIRES[wire % WIRES.len()] as char;
+                *cell = if *cell == ' ' || *cell == symbol { symbol } else { '#' };
+            },
+            |cell, mark| {
+                *cell = match mark {
+                    Mark::Origin => 'o',
+                    Mark::Crossing => '*',
+                    Mark::Closest => 'C',
+                    Mark::Fastest => 'D',
+                }
+            },
+        );
+
+        let mut result = String::new();
+        for row in cells.chunks(width) {
+            result.push_str(row.iter().collect::<String>().trim_end());
+            result.push('\n');
+        }
+
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mark {
+    Origin,
+    Crossing,
+    Closest,
+    Fastest,
+}
+
+impl Mark {
+    fn color(self) -> [u8; 3] {
+        match self {
+            Mark::Origin => ORIGIN,
+            Mark::Crossing => CROSSING,
+            Mark::Closest => CLOSEST,
+            Mark::Fastest => FASTEST,
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::Direction;
+
+// (row, col, layer), with up being negative rows like the original grid.
+// Wires that never move forward or back stay on layer 0.
+pub type Point = (isize, isize, isize);
+
+fn coords(p: Point) -> [isize; 3] {
+    [p.0, p.1, p.2]
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub start: Point,
+    pub dir: Direction,
+    pub len: usize,
+    // Steps the wire took to reach `start`
+    pub delay: usize,
+}
+
+impl Segment {
+    pub fn at(&self, steps: usize) -> Point {
+        let (dr, dc, dl) = self.dir.delta();
+        let steps = steps as isize;
+        (self.start.0 + dr * steps, self.start.1 + dc * steps, self.start.2 + dl * steps)
+    }
+
+    pub fn end(&self) -> Point {
+        self.at(self.len)
+    }
+
+    // The lowest and highest corners of the box this segment fits in
+    fn bounds(&self) -> (Point, Point) {
+        let (start, end) = (self.start, self.end());
+        (
+            (start.0.min(end.0), start.1.min(end.1), start.2.min(end.2)),
+            (start.0.max(end.0), start.1.max(end.1), start.2.max(end.2)),
+        )
+    }
+
+    // How many steps along this segment's line `point` is, if it's on the line at all
+    fn line_steps(&self, point: Point) -> Option<isize> {
+        let offset = coords(point).iter().zip(coords(self.start).iter()).map(|(p, s)| p - s).collect::<Vec<_>>();
+        let delta = coords(self.dir.delta());
+
+        // Every delta is -1, 0 or 1, so dividing by it is multiplying by it
+        let steps = offset.iter().zip(delta.iter()).find(|(_, &d)| d != 0).map(|(o, d)| o * d)?;
+        let on_line = offset.iter().zip(delta.iter()).all(|(o, d)| *o == d * steps);
+
+        if on_line { Some(steps) } else { None }
+    }
+
+    // How many steps into this segment `point` is, if it's on it
+    pub fn steps_to(&self, point: Point) -> Option<usize> {
+        self.line_steps(point).filter(|&steps| 0 <= steps && steps <= self.len as isize).map(|steps| steps as usize)
+    }
+
+    // Every point on both segments: nothing, one point where they cross, or a run
+    // of points where parallel segments overlap. Diagonals that cross between
+    // grid points don't share a point, so they don't count.
+    pub fn crossings(&self, other: &Segment) -> Option<Overlap> {
+        let (a, b) = (coords(self.dir.delta()), coords(other.dir.delta()));
+
+        if a == b || a.iter().zip(b.iter()).all(|(x, y)| *x == -y) {
+            let (from, to) = (self.line_steps(other.start)?, self.line_steps(other.end())?);
+            let (from, to) = (from.min(to).max(0), from.max(to).min(self.len as isize));
+            if from > to {
+                return None;
+            }
+            return Some(Overlap { segment: *self, steps: from as usize..=to as usize });
+        }
+
+        // Solve start_a + t * a = start_b + u * b using two axes the directions
+        // differ on, then check the answer against all three
+        let q = coords(other.start).iter().zip(coords(self.start).iter()).map(|(o, s)| o - s).collect::<Vec<_>>();
+
+        for (i, j) in [(0, 1), (0, 2), (1, 2)].iter().copied() {
+            let det = b[i] * a[j] - a[i] * b[j];
+            if det == 0 {
+                continue;
+            }
+
+            let (t, u) = (b[i] * q[j] - q[i] * b[j], a[i] * q[j] - q[i] * a[j]);
+            if t % det != 0 || u % det != 0 {
+                return None;
+            }
+
+            let (t, u) = (t / det, u / det);
+            if t < 0 || u < 0 || t > self.len as isize || u > other.len as isize || self.at(t as usize) != other.at(u as usize) {
+                return None;
+            }
+
+            return Some(Overlap { segment: *self, steps: t as usize..=t as usize });
+        }
+
+        None
+    }
+}
+
+// The points two segments share, as a range of steps along the first, so a
+// long overlap costs nothing until its points are asked for
+#[derive(Debug, Clone)]
+pub struct Overlap {
+    pub segment: Segment,
+    pub steps: RangeInclusive<usize>,
+}
+
+impl Overlap {
+    pub fn len(&self) -> usize {
+        self.steps.end() - self.steps.start() + 1
+    }
+
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.steps.clone().map(move |steps| self.segment.at(steps))
+    }
+}
+
+pub fn segments(dirs: &[(Direction, usize)]) -> Vec<Segment> {
+    let mut result = Vec::with_capacity(dirs.len());
+    let mut start = (0, 0, 0);
+    let mut delay = 0;
+
+    for &(dir, len) in dirs {
+        let segment = Segment { start, dir, len, delay };
+        start = segment.end();
+        delay += len;
+        result.push(segment);
+    }
+
+    result
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crossing {
+    pub pos: Point,
+    // The fewest steps each wire takes to get here, or None if it never does
+    pub delays: Vec<Option<usize>>,
+}
+
+impl Crossing {
+    // How many different wires pass through this point
+    pub fn wires(&self) -> usize {
+        self.delays.iter().flatten().count()
+    }
+
+    pub fn total_delay(&self) -> usize {
+        self.delays.iter().flatten().sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // Only report points where at least this many wires cross, counting from 2
+    pub min_wires: usize,
+    // Count each separate pass of a wire through a point, so one wire
+    // crossing itself is enough to make a crossing
+    pub self_crossings: bool,
+    // Every point where two segments meet is kept until the end, and long
+    // parallel overlaps meet at every point along them, so this caps how
+    // many points (counted once per pair of segments) can be kept
+    pub max_points: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { min_wires: 2, self_crossings: false, max_points: 10_000_000 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TooManyPoints(pub usize);
+
+impl fmt::Display for TooManyPoints {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wires overlap at more than {} points", self.0)
+    }
+}
+
+// Every point other than the origin where enough wires cross.
+// Sweeps across columns so each segment is only compared with the
+// segments whose column range overlaps its own.
+pub fn intersect(wires: &[Vec<Segment>], options: &Options) -> Result<Vec<Crossing>, TooManyPoints> {
+    let mut order = wires.iter().enumerate()
+        .flat_map(|(wire, segments)| segments.iter().enumerate().map(move |(idx, s)| (wire, idx, s)))
+        .collect::<Vec<_>>();
+    order.sort_by_key(|(_, _, s)| s.bounds().0 .1);
+
+    // Every segment that touches each candidate point, as (wire, index)
+    let mut active: Vec<(usize, usize, &Segment)> = Vec::new();
+    let mut found: HashMap<Point, Vec<(usize, usize)>> = HashMap::new();
+    let mut budget = options.max_points;
+
+    for (wire, idx, segment) in order {
+        let left = segment.bounds().0 .1;
+        active.retain(|(_, _, s)| s.bounds().1 .1 >= left);
+
+        for &(other_wire, other_idx, other) in active.iter() {
+            if other_wire == wire && !options.self_crossings {
+                continue;
+            }
+
+            let overlap = match segment.crossings(other) {
+                Some(overlap) => overlap,
+                None => continue,
+            };
+
+            budget = budget.checked_sub(overlap.len()).ok_or(TooManyPoints(options.max_points))?;
+
+            for pos in overlap.points() {
+                if pos != (0, 0, 0) {
+                    let touching = found.entry(pos).or_default();
+                    touching.push((wire, idx));
+                    touching.push((other_wire, other_idx));
+                }
+            }
+        }
+
+        active.push((wire, idx, segment));
+    }
+
+    let mut result = Vec::new();
+
+    for (pos, mut touching) in found {
+        touching.sort_unstable();
+        touching.dedup();
+
+        let mut delays = vec![None; wires.len()];
+        let mut passes = 0;
+
+        for &(wire, idx) in touching.iter() {
+            let segment = &wires[wire][idx];
+            let delay = segment.delay + segment.steps_to(pos).unwrap();
+            delays[wire] = Some(delays[wire].map_or(delay, |d: usize| d.min(delay)));
+
+            // A corner belongs to the segments on both sides of it, but it's only one pass
+            if idx == 0 || pos != segment.start {
+                passes += 1;
+            }
+        }
+
+        let crossing = Crossing { pos, delays };
+        let count = if options.self_crossings { passes } else { crossing.wires() };
+
+        // Anything under 2 would report points only one pass goes through
+        if count >= options.min_wires.max(2) {
+            result.push(crossing);
+        }
+    }
+
+    result.sort_by_key(|c| c.pos);
+
+    Ok(result)
+}
@@ -0,0 +1,338 @@
+use super::*;
+use segment::{Crossing, Point, Segment};
+
+fn wires(text: &str, moves: Moves) -> Vec<Vec<Segment>> {
+    parse::parse_wires(text, moves).unwrap().iter().map(|dirs| segment::segments(dirs)).collect()
+}
+
+fn crossings(text: &str) -> Vec<Crossing> {
+    segment::intersect(&wires(text, Moves::Four), &Options::default()).unwrap()
+}
+
+// The lowest Manhattan distance from the origin and the lowest combined delay
+fn answers(text: &str) -> (isize, usize) {
+    let ints = crossings(text);
+    let distance = ints.iter().map(|c| c.pos.0.abs() + c.pos.1.abs() + c.pos.2.abs()).min().unwrap();
+    let delay = ints.iter().map(|c| c.total_delay()).min().unwrap();
+    (distance, delay)
+}
+
+fn segment(start: Point, dir: Direction, len: usize) -> Segment {
+    Segment { start, dir, len, delay: 0 }
+}
+
+fn shared(a: &Segment, b: &Segment) -> Vec<Point> {
+    a.crossings(b).map(|o| o.points().collect()).unwrap_or_default()
+}
+
+#[test]
+fn perpendicular_segments() {
+    let across = segment((0, 0, 0), Direction::Right, 10);
+    let down = segment((-3, 4, 0), Direction::Down, 6);
+    assert_eq!(shared(&across, &down), vec![(0, 4, 0)]);
+    assert_eq!(shared(&down, &across), vec![(0, 4, 0)]);
+
+    // Touching at an end still counts, stopping short doesn't
+    assert_eq!(shared(&across, &segment((-3, 10, 0), Direction::Down, 3)), vec![(0, 10, 0)]);
+    assert_eq!(shared(&across, &segment((-3, 11, 0), Direction::Down, 6)), vec![]);
+    assert_eq!(shared(&across, &segment((-3, 4, 0), Direction::Down, 2)), vec![]);
+}
+
+#[test]
+fn collinear_overlap() {
+    let a = segment((2, 0, 0), Direction::Right, 10);
+    let b = segment((2, 13, 0), Direction::Left, 6);
+
+    let overlap = a.crossings(&b).unwrap();
+    assert_eq!(overlap.len(), 4);
+    assert_eq!(overlap.points().collect::<Vec<_>>(), vec![(2, 7, 0), (2, 8, 0), (2, 9, 0), (2, 10, 0)]);
+    assert_eq!(shared(&b, &a), vec![(2, 10, 0), (2, 9, 0), (2, 8, 0), (2, 7, 0)]);
+
+    // A long overlap is only a range until it's walked
+    let long = segment((0, 0, 0), Direction::Up, usize::MAX / 4);
+    assert_eq!(long.crossings(&long).unwrap().len(), usize::MAX / 4 + 1);
+}
+
+#[test]
+fn parallel_disjoint() {
+    let a = segment((0, 0, 0), Direction::Right, 5);
+    assert_eq!(shared(&a, &segment((1, 0, 0), Direction::Right, 5)), vec![]);
+    assert_eq!(shared(&a, &segment((0, 6, 0), Direction::Right, 5)), vec![]);
+    assert_eq!(shared(&a, &segment((0, 12, 0), Direction::Left, 5)), vec![]);
+}
+
+#[test]
+fn zero_length_segments() {
+    let a = segment((0, 0, 0), Direction::Right, 5);
+    assert_eq!(shared(&a, &segment((0, 3, 0), Direction::Up, 0)), vec![(0, 3, 0)]);
+    assert_eq!(shared(&a, &segment((0, 3, 0), Direction::Left, 0)), vec![(0, 3, 0)]);
+    assert_eq!(shared(&segment((0, 3, 0), Direction::Down, 0), &a), vec![(0, 3, 0)]);
+    assert_eq!(shared(&a, &segment((1, 3, 0), Direction::Up, 0)), vec![]);
+}
+
+#[test]
+fn published_examples() {
+    assert_eq!(answers("R8,U5,L5,D3\nU7,R6,D4,L4"), (6, 30));
+    assert_eq!(answers("R75,D30,R83,U83,L12,D49,R71,U7,L72\nU62,R66,U55,R34,D71,R55,D58,R83"), (159, 610));
+    assert_eq!(answers("R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51\nU98,R91,D20,R16,D67,R40,U7,R15,U6,R7"), (135, 410));
+}
+
+#[test]
+fn puzzle_answers() {
+    assert_eq!(answers(&std::fs::read_to_string("./data.txt").unwrap()), (5319, 122514));
+}
+
+#[test]
+fn overlaps_past_the_limit() {
+    let wires = wires("R100\nR100", Moves::Four);
+    let options = Options { max_points: 50, ..Options::default() };
+    assert_eq!(segment::intersect(&wires, &options), Err(segment::TooManyPoints(50)));
+    assert_eq!(segment::intersect(&wires, &Options::default()).unwrap().len(), 100);
+}
+
+fn crossings_with(text: &str, min_wires: usize, self_crossings: bool) -> Vec<Crossing> {
+    let options = Options { min_wires, self_crossings, ..Options::default() };
+    segment::intersect(&wires(text, Moves::Four), &options).unwrap()
+}
+
+#[test]
+fn crossing_of_three_wires() {
+    // The last two share a column, and the first crosses it in the middle
+    let text = "R5\nU1,R3,D2\nD1,R3,U2";
+
+    let all = crossings_with(text, 2, false);
+    assert_eq!(all.iter().map(|c| c.pos).collect::<Vec<_>>(), vec![(-1, 3, 0), (0, 3, 0), (1, 3, 0)]);
+
+    let three = crossings_with(text, 3, false);
+    assert_eq!(three, vec![Crossing { pos: (0, 3, 0), delays: vec![Some(3), Some(5), Some(5)] }]);
+    assert_eq!(crossings_with(text, 4, false), vec![]);
+}
+
+#[test]
+fn self_crossings() {
+    // Comes back down through its own first move
+    let text = "R3,U2,L1,D4";
+
+    assert_eq!(crossings_with(text, 2, false), vec![]);
+    assert_eq!(crossings_with(text, 2, true), vec![Crossing { pos: (0, 2, 0), delays: vec![Some(2)] }]);
+    assert_eq!(crossings_with(text, 3, true), vec![]);
+}
+
+#[test]
+fn corners_are_one_pass() {
+    assert_eq!(crossings_with("R3,U2,L5,D1", 2, true), vec![]);
+
+    // Along a shared column every point is one pass per wire, the corner at (0, 3) included
+    let corner = crossings_with("R3,U2\nU3,R3,D5", 2, true);
+    assert_eq!(corner, vec![
+        Crossing { pos: (-2, 3, 0), delays: vec![Some(5), Some(7)] },
+        Crossing { pos: (-1, 3, 0), delays: vec![Some(4), Some(8)] },
+        Crossing { pos: (0, 3, 0), delays: vec![Some(3), Some(9)] },
+    ]);
+}
+
+#[test]
+fn min_wires_below_two() {
+    let text = "R8,U5,L5,D3\nU7,R6,D4,L4";
+    let expected = crossings_with(text, 2, false);
+    assert_eq!(expected.len(), 2);
+
+    for min_wires in 0..2 {
+        assert_eq!(crossings_with(text, min_wires, false), expected);
+        assert_eq!(crossings_with("R3,U2,L5,D1", min_wires, true), vec![]);
+    }
+}
+
+fn with_example_scene(check: impl FnOnce(&Scene)) {
+    let wires = wires("R8,U5,L5,D3\nU7,R6,D4,L4", Moves::Four);
+    let ints = segment::intersect(&wires, &Options::default()).unwrap();
+    check(&Scene::new(&wires, &ints));
+}
+
+#[test]
+fn ascii_example() {
+    // One character per grid point, matching the picture in the puzzle
+    with_example_scene(|scene| assert_eq!(scene.ascii(9, 8), concat!(
+        "2222222\n",
+        "2     2\n",
+        "2  111D11\n",
+        "2  1  2 1\n",
+        "2 2C222 1\n",
+        "2  1    1\n",
+        "2       1\n",
+        "o11111111\n",
+    )));
+}
+
+#[test]
+fn png_dimensions() {
+    // 9 columns by 8 rows, scaled so the longer side is the size asked for
+    with_example_scene(|scene| {
+        let data = scene.png(90);
+        let decoder = png::Decoder::new(&data[..]);
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (90, 80));
+        assert_eq!(info.color_type, png::ColorType::Rgb);
+    });
+}
+
+#[test]
+fn render_spans_all_of_isize() {
+    // Two wires out to opposite ends, wider than any isize can count
+    let wires = vec![
+        segment::segments(&[(Direction::Right, isize::MAX as usize)]),
+        segment::segments(&[(Direction::Left, isize::MAX as usize)]),
+    ];
+    let scene = Scene::new(&wires, &[]);
+
+    let width = 2 * isize::MAX as i128 + 1;
+    assert!(scene.svg().contains(&format!(r#"viewBox="-1 -1 {} 2""#, width + 1)));
+    assert_eq!(scene.ascii(9, 1), "2222o1111\n");
+
+    let data = scene.png(100);
+    let reader = png::Decoder::new(&data[..]).read_info().unwrap();
+    assert_eq!((reader.info().width, reader.info().height), (100, 1));
+}
+
+// Far enough apart that every metric orders them differently
+fn sample_crossings() -> Vec<Crossing> {
+    vec![
+        Crossing { pos: (3, 4, 0), delays: vec![Some(10), Some(10)] },
+        Crossing { pos: (5, 0, 0), delays: vec![Some(3), Some(4)] },
+        Crossing { pos: (-4, -4, 0), delays: vec![Some(1), None] },
+    ]
+}
+
+fn ranked(crossings: &[Crossing], metric: &Metric, reference: Point) -> Vec<(Point, f64)> {
+    rank::rank(crossings, metric, reference).iter().map(|(c, d)| (c.pos, *d)).collect()
+}
+
+#[test]
+fn rank_by_each_metric() {
+    let crossings = sample_crossings();
+    let origin = (0, 0, 0);
+
+    assert_eq!(ranked(&crossings, &Metric::Manhattan, origin), vec![((5, 0, 0), 5.0), ((3, 4, 0), 7.0), ((-4, -4, 0), 8.0)]);
+    // Ties keep their original order
+    assert_eq!(ranked(&crossings, &Metric::Chebyshev, origin), vec![((3, 4, 0), 4.0), ((-4, -4, 0), 4.0), ((5, 0, 0), 5.0)]);
+    assert_eq!(ranked(&crossings, &Metric::Euclidean, origin), vec![((3, 4, 0), 5.0), ((5, 0, 0), 5.0), ((-4, -4, 0), 32f64.sqrt())]);
+    assert_eq!(ranked(&crossings, &Metric::Delay, origin), vec![((-4, -4, 0), 1.0), ((5, 0, 0), 7.0), ((3, 4, 0), 20.0)]);
+
+    let column = Metric::custom(|c, _| c.pos.1 as f64);
+    assert_eq!(ranked(&crossings, &column, origin), vec![((-4, -4, 0), -4.0), ((5, 0, 0), 0.0), ((3, 4, 0), 4.0)]);
+}
+
+#[test]
+fn rank_by_parsed_sum() {
+    let crossings = sample_crossings();
+
+    let metric = "manhattan+delay".parse::<Metric>().unwrap();
+    assert_eq!(ranked(&crossings, &metric, (0, 0, 0)), vec![((-4, -4, 0), 9.0), ((5, 0, 0), 12.0), ((3, 4, 0), 27.0)]);
+
+    assert!("manhattan+nearest".parse::<Metric>().is_err());
+    assert!("".parse::<Metric>().is_err());
+}
+
+#[test]
+fn rank_from_another_point() {
+    let crossings = sample_crossings();
+
+    assert_eq!(ranked(&crossings, &Metric::Manhattan, (-4, -4, 0)), vec![((-4, -4, 0), 0.0), ((5, 0, 0), 13.0), ((3, 4, 0), 15.0)]);
+    assert_eq!(ranked(&crossings, &Metric::Chebyshev, (5, 0, 2)), vec![((5, 0, 0), 2.0), ((3, 4, 0), 4.0), ((-4, -4, 0), 9.0)]);
+    // Delay doesn't depend on where it's measured from
+    assert_eq!(ranked(&crossings, &Metric::Delay, (100, 100, 100)), ranked(&crossings, &Metric::Delay, (0, 0, 0)));
+}
+
+#[test]
+fn diagonal_crossings() {
+    let rising = segment((0, 0, 0), Direction::UpRight, 4);
+
+    assert_eq!(shared(&rising, &segment((-4, 0, 0), Direction::DownRight, 4)), vec![(-2, 2, 0)]);
+    assert_eq!(shared(&rising, &segment((-3, 0, 0), Direction::Right, 5)), vec![(-3, 3, 0)]);
+    assert_eq!(shared(&segment((-1, 4, 0), Direction::Down, 5), &rising), vec![]);
+    assert_eq!(shared(&rising, &segment((2, -2, 0), Direction::UpRight, 5)), vec![(0, 0, 0), (-1, 1, 0), (-2, 2, 0), (-3, 3, 0)]);
+
+    // These would meet at (-1.5, 1.5), which isn't a grid point
+    assert_eq!(shared(&rising, &segment((-3, 0, 0), Direction::DownRight, 4)), vec![]);
+    assert_eq!(shared(&segment((-3, 0, 0), Direction::DownRight, 4), &rising), vec![]);
+}
+
+#[test]
+fn crossings_between_layers() {
+    let up = segment((0, 0, 0), Direction::Forward, 5);
+
+    assert_eq!(shared(&up, &segment((0, -2, 3), Direction::Right, 4)), vec![(0, 0, 3)]);
+    assert_eq!(shared(&up, &segment((0, 0, 5), Direction::Back, 3)), vec![(0, 0, 2), (0, 0, 3), (0, 0, 4), (0, 0, 5)]);
+    assert_eq!(shared(&up, &segment((1, 0, 3), Direction::Right, 4)), vec![]);
+    // Same row and column on different layers
+    assert_eq!(shared(&segment((0, 0, 1), Direction::Right, 4), &segment((0, 2, 2), Direction::Left, 4)), vec![]);
+
+    let wires = wires("F3,R2\nR1,F3,L2", Moves::Space);
+    assert_eq!(segment::intersect(&wires, &Options::default()).unwrap(), vec![
+        Crossing { pos: (0, 0, 3), delays: vec![Some(3), Some(5)] },
+        Crossing { pos: (0, 1, 3), delays: vec![Some(4), Some(4)] },
+    ]);
+}
+
+#[test]
+fn parse_bounds_coordinates() {
+    use parse::{ErrorKind, MAX_COORD};
+
+    // Opposite wires right at the limit parse, and the picture between them fits
+    let text = format!("R{}\nL{}", MAX_COORD, MAX_COORD);
+    let wires = wires(&text, Moves::Four);
+    assert_eq!((wires[0][0].end(), wires[1][0].end()), ((0, MAX_COORD, 0), (0, -MAX_COORD, 0)));
+    assert_eq!(Scene::new(&wires, &[]).ascii(9, 1), "2222o1111\n");
+
+    // One more step is too far, even on the diagonal or after doubling back
+    for (text, moves) in [
+        (format!("L{}", MAX_COORD + 1), Moves::Four),
+        (format!("SW{}", MAX_COORD + 1), Moves::Eight),
+        (format!("R2,L{}", MAX_COORD + 3), Moves::Four),
+        (format!("B{}", MAX_COORD + 1), Moves::Space),
+    ].iter() {
+        assert_eq!(parse::parse_wires(text, *moves).unwrap_err().kind, ErrorKind::OutOfBounds, "{:?}", text);
+    }
+}
+
+#[test]
+fn parse_errors() {
+    use parse::{ErrorKind, ParseError};
+
+    let error = |text: &str, moves: Moves| parse::parse_wires(text, moves).unwrap_err();
+    let big = "99999999999999999999999";
+
+    let cases = vec![
+        ("R1\nU2,X2", Moves::Four, 2, 2, "X2", ErrorKind::UnknownDirection("X".to_string())),
+        ("R1,,U2", Moves::Four, 1, 2, "", ErrorKind::EmptyMove),
+        ("R1\nU2,L3, ,D4", Moves::Four, 2, 3, "", ErrorKind::EmptyMove),
+        ("R1,U", Moves::Four, 1, 2, "U", ErrorKind::MissingLength),
+        ("R1x", Moves::Four, 1, 1, "R1x", ErrorKind::BadLength("1x".to_string())),
+        ("R5\nL2,D99999999999999999999999", Moves::Four, 2, 2, "D99999999999999999999999", ErrorKind::Overflow(big.to_string())),
+        ("R9223372036854775807,U1", Moves::Four, 1, 1, "R9223372036854775807", ErrorKind::OutOfBounds),
+        ("R4611686018427387903,L4611686018427387903,R4611686018427387903", Moves::Four, 1, 3, "R4611686018427387903", ErrorKind::WireTooLong),
+        ("R1\nNE2", Moves::Four, 2, 1, "NE2", ErrorKind::NotAllowed("NE".to_string(), Moves::Four)),
+        ("F2", Moves::Eight, 1, 1, "F2", ErrorKind::NotAllowed("F".to_string(), Moves::Eight)),
+        // Blank lines don't count as wires
+        ("R1\n\n   \nX2", Moves::Four, 2, 1, "X2", ErrorKind::UnknownDirection("X".to_string())),
+    ];
+
+    for (text, moves, wire, token, bad, kind) in cases {
+        assert_eq!(error(text, moves), ParseError { wire, token, text: bad.to_string(), kind }, "{:?}", text);
+    }
+
+    assert_eq!(error("R1,U", Moves::Four).to_string(), r#"wire 1, move 2 ("U"): missing length"#);
+}
+
+#[test]
+fn parse_skips_blank_lines_and_trailing_commas() {
+    let wires = parse::parse_wires("\nR2,U3,\n\n  \n L4 , NE1 \n", Moves::Eight).unwrap();
+    assert_eq!(wires, vec![
+        vec![(Direction::Right, 2), (Direction::Up, 3)],
+        vec![(Direction::Left, 4), (Direction::UpRight, 1)],
+    ]);
+
+    // Only one trailing comma is allowed
+    assert_eq!(parse::parse_wires("R2,,", Moves::Four).unwrap_err().kind, parse::ErrorKind::EmptyMove);
+}
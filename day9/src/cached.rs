@@ -0,0 +1,81 @@
+use crate::{pad_memory, Opcode, OpcodeRoot, ProgramState, RunResult};
+use crate::dialect::Dialect;
+use crate::extension::Extensions;
+
+// An interpreter that decodes each instruction once and reuses it until
+// the program writes over the words it was decoded from.
+pub struct CachedProgram {
+    pub state: ProgramState,
+    decoded: Vec<Option<Opcode>>,
+}
+
+impl CachedProgram {
+    pub fn new(state: ProgramState) -> CachedProgram {
+        let decoded = vec![None; state.program.len()];
+        CachedProgram { state, decoded }
+    }
+
+    pub fn step(&mut self) -> (Option<i64>, bool) {
+        let CachedProgram { state, decoded } = self;
+        let ProgramState { program, input, input_idx, pc, relative_base, extensions, dialect } = state;
+
+        if let Some(ext) = extensions.get((program[*pc] % 100) as u32) {
+            let (new_pc, out, consumed) = ext.execute(program, *pc, &input[*input_idx..], relative_base);
+            *pc = new_pc.unwrap_or(*pc + 1 + ext.arg_count);
+            *input_idx += consumed;
+            // Handlers can touch any memory, so nothing decoded can be trusted
+            decoded.iter_mut().for_each(|d| *d = None);
+            return (out, false);
+        }
+
+        let opcode = decoded[*pc].get_or_insert_with(|| Opcode::parse(&program[*pc..]));
+        if let Err(e) = dialect.check(opcode, *pc) {
+            panic!("{}", e);
+        }
+        let target = opcode.write_target(*relative_base);
+
+        let (new_pc, out) = opcode.execute(program, &mut input[*input_idx..].iter(), relative_base);
+
+        let root = opcode.root;
+
+        if let Some(new_pc) = new_pc {
+            *pc = new_pc;
+        } else {
+            *pc += 1 + root.arg_count();
+        }
+
+        if root == OpcodeRoot::Input {
+            *input_idx += 1;
+        }
+
+        // Instructions are at most four words long, so a store can only
+        // land inside one that starts up to three words earlier
+        if let Some(target) = target {
+            let end = (target + 1).min(decoded.len());
+            decoded[target.saturating_sub(3)..end].iter_mut().for_each(|d| *d = None);
+        }
+
+        (out, root == OpcodeRoot::Halt)
+    }
+}
+
+pub fn run_prog_limited(mut program: Vec<i64>, input: &[i64], max_steps: usize) -> RunResult {
+    pad_memory(&mut program);
+
+    let state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+    let mut cached = CachedProgram::new(state);
+
+    let mut output = Vec::new();
+
+    for _ in 0..max_steps {
+        let (out, halted) = cached.step();
+
+        output.extend(out);
+
+        if halted {
+            return RunResult { output, memory: cached.state.program, halted: true };
+        }
+    }
+
+    RunResult { output, memory: cached.state.program, halted: false }
+}
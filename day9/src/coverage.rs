@@ -0,0 +1,175 @@
+use std::fmt::Write;
+
+use crate::{pad_memory, step_access, step_prog, Access, ProgramState};
+use crate::dialect::Dialect;
+use crate::extension::Extensions;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Unused,
+    Code,
+    // Executed, but also written to at some point
+    ModifiedCode,
+    Data,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Unused => "unused",
+            Kind::Code => "code",
+            Kind::ModifiedCode => "modified code",
+            Kind::Data => "data",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Kind::Unused => "#dddddd",
+            Kind::Code => "#2060c0",
+            Kind::ModifiedCode => "#a030c0",
+            Kind::Data => "#e08020",
+        }
+    }
+}
+
+// How many times each address was executed as part of an instruction, read as data, and written
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    pub executed: Vec<u64>,
+    pub read: Vec<u64>,
+    pub written: Vec<u64>,
+}
+
+impl Coverage {
+    pub fn new(size: usize) -> Coverage {
+        Coverage {
+            executed: vec![0; size],
+            read: vec![0; size],
+            written: vec![0; size],
+        }
+    }
+
+    pub fn record(&mut self, access: &Access) {
+        for addr in access.pc..access.pc + access.size {
+            self.executed[addr] += 1;
+        }
+        for &addr in access.reads.iter() {
+            self.read[addr] += 1;
+        }
+        for &addr in access.writes.iter() {
+            self.written[addr] += 1;
+        }
+    }
+
+    pub fn kind(&self, addr: usize) -> Kind {
+        match (self.executed[addr], self.read[addr], self.written[addr]) {
+            (0, 0, 0) => Kind::Unused,
+            (0, _, _) => Kind::Data,
+            (_, _, 0) => Kind::Code,
+            _ => Kind::ModifiedCode,
+        }
+    }
+
+    fn total(&self, addr: usize) -> u64 {
+        self.executed[addr] + self.read[addr] + self.written[addr]
+    }
+
+    // One past the last address that is part of the image or was touched at all
+    fn extent(&self, image_len: usize) -> usize {
+        let touched = (0..self.executed.len()).rev().find(|&a| self.total(a) != 0).map_or(0, |a| a + 1);
+        touched.max(image_len)
+    }
+
+    // A table of contiguous regions that were used the same way
+    pub fn report(&self, image_len: usize) -> String {
+        let mut result = String::new();
+        writeln!(result, "{:>13} {:>14} {:>10} {:>10} {:>10}", "addresses", "kind", "executed", "reads", "writes").unwrap();
+
+        let end = self.extent(image_len);
+        let mut start = 0;
+
+        while start < end {
+            let kind = self.kind(start);
+            let stop = (start..end).find(|&a| self.kind(a) != kind).unwrap_or(end);
+
+            let sum = |counts: &[u64]| counts[start..stop].iter().sum::<u64>();
+            writeln!(
+                result, "{:>13} {:>14} {:>10} {:>10} {:>10}",
+                format!("{}-{}", start, stop - 1), kind.label(), sum(&self.executed), sum(&self.read), sum(&self.written),
+            ).unwrap();
+
+            start = stop;
+        }
+
+        for kind in [Kind::Code, Kind::ModifiedCode, Kind::Data, Kind::Unused].iter() {
+            let count = (0..end).filter(|&a| self.kind(a) == *kind).count();
+            writeln!(result, "{}: {} words", kind.label(), count).unwrap();
+        }
+
+        result
+    }
+
+    // A grid with one cell per address, coloured by kind and shaded by how often it was touched
+    pub fn svg(&self, image_len: usize, columns: usize) -> String {
+        const CELL: usize = 10;
+
+        let end = self.extent(image_len);
+        let rows = end.div_ceil(columns);
+        let max = (0..end).map(|a| self.total(a)).max().unwrap_or(0);
+
+        let mut result = String::new();
+        writeln!(
+            result, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+            columns * CELL, rows * CELL + 2 * CELL,
+        ).unwrap();
+
+        for addr in 0..end {
+            let kind = self.kind(addr);
+            let opacity = if kind == Kind::Unused {
+                1.0
+            } else {
+                0.25 + 0.75 * ((self.total(addr) + 1) as f64).ln() / ((max + 1) as f64).ln()
+            };
+
+            writeln!(
+                result,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="{:.2}"><title>{}: {} (exec {}, read {}, write {})</title></rect>"#,
+                (addr % columns) * CELL, (addr / columns) * CELL, CELL, CELL, kind.color(), opacity,
+                addr, kind.label(), self.executed[addr], self.read[addr], self.written[addr],
+            ).unwrap();
+        }
+
+        for (i, kind) in [Kind::Code, Kind::ModifiedCode, Kind::Data, Kind::Unused].iter().enumerate() {
+            let x = i * columns * CELL / 4;
+            let y = rows * CELL + CELL / 2;
+            writeln!(result, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, x, y, CELL, CELL, kind.color()).unwrap();
+            writeln!(result, r#"<text x="{}" y="{}" font-size="{}">{}</text>"#, x + CELL + 2, y + CELL - 1, CELL, kind.label()).unwrap();
+        }
+
+        writeln!(result, "</svg>").unwrap();
+
+        result
+    }
+}
+
+pub fn run_with_coverage(mut program: Vec<i64>, input: &[i64]) -> (Vec<i64>, Coverage) {
+    pad_memory(&mut program);
+
+    let mut coverage = Coverage::new(program.len());
+    let mut output = Vec::new();
+
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+
+    loop {
+        coverage.record(&step_access(&state));
+
+        let (out, halted) = step_prog(&mut state);
+
+        output.extend(out);
+
+        if halted {
+            return (output, coverage);
+        }
+    }
+}
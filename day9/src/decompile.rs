@@ -0,0 +1,342 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::{Opcode, OpcodeRoot, ParamMode};
+
+#[derive(Debug)]
+enum Instr {
+    Plain(Opcode),
+    // `[rb+0] = ret; jump target` where `ret` is the address after the jump
+    Call { target: usize, ret: usize },
+    // A jump to the address stored at [rb+0]
+    Return,
+    Invalid(i64),
+}
+
+struct Function {
+    entry: usize,
+    // Instructions and their size in words, keyed by address
+    code: BTreeMap<usize, (Instr, usize)>,
+}
+
+fn always_taken(opcode: &Opcode) -> bool {
+    match (opcode.root, opcode.params[0]) {
+        (OpcodeRoot::JumpTrue, (ParamMode::Immediate, c)) => c != 0,
+        (OpcodeRoot::JumpFalse, (ParamMode::Immediate, c)) => c == 0,
+        _ => false,
+    }
+}
+
+fn is_jump(opcode: &Opcode) -> bool {
+    opcode.root == OpcodeRoot::JumpTrue || opcode.root == OpcodeRoot::JumpFalse
+}
+
+fn immediate_target(opcode: &Opcode) -> Option<usize> {
+    match opcode.params[1] {
+        (ParamMode::Immediate, t) if t >= 0 => Some(t as usize),
+        _ => None,
+    }
+}
+
+fn decode(program: &[i64], pc: usize) -> (Instr, usize) {
+    let opcode = match program.get(pc..).and_then(Opcode::try_parse) {
+        Some(opcode) => opcode,
+        None => return (Instr::Invalid(program.get(pc).copied().unwrap_or(0)), 1),
+    };
+
+    // Storing a constant return address to [rb+0] and then jumping is a call
+    let stored = match (opcode.root, opcode.params.get(..3)) {
+        (OpcodeRoot::Add, Some(&[(ParamMode::Immediate, a), (ParamMode::Immediate, b), (ParamMode::Relative, 0)])) => Some(a + b),
+        (OpcodeRoot::Multiply, Some(&[(ParamMode::Immediate, a), (ParamMode::Immediate, b), (ParamMode::Relative, 0)])) => Some(a * b),
+        _ => None,
+    };
+
+    if let Some(ret) = stored {
+        let next = program.get(pc + 4..).and_then(Opcode::try_parse);
+        if let Some(jump) = next.filter(|j| is_jump(j) && always_taken(j)) {
+            if let Some(target) = immediate_target(&jump) {
+                if ret == (pc + 7) as i64 {
+                    return (Instr::Call { target, ret: pc + 7 }, 7);
+                }
+            }
+        }
+    }
+
+    if is_jump(&opcode) && always_taken(&opcode) && opcode.params[1] == (ParamMode::Relative, 0) {
+        return (Instr::Return, 3);
+    }
+
+    let size = opcode.size();
+    (Instr::Plain(opcode), size)
+}
+
+// Finds every instruction reachable from `entry` without entering calls,
+// and adds each call's target to `entries`
+fn walk(program: &[i64], entry: usize, entries: &mut BTreeSet<usize>) -> Function {
+    let mut code = BTreeMap::new();
+    let mut pending = vec![entry];
+
+    while let Some(pc) = pending.pop() {
+        if code.contains_key(&pc) || pc >= program.len() {
+            continue;
+        }
+
+        let (instr, size) = decode(program, pc);
+
+        match &instr {
+            Instr::Call { target, ret } => {
+                entries.insert(*target);
+                pending.push(*ret);
+            }
+            Instr::Return | Instr::Invalid(_) => {}
+            Instr::Plain(opcode) if opcode.root == OpcodeRoot::Halt => {}
+            Instr::Plain(opcode) if is_jump(opcode) => {
+                pending.extend(immediate_target(opcode));
+                if !always_taken(opcode) {
+                    pending.push(pc + size);
+                }
+            }
+            Instr::Plain(_) => pending.push(pc + size),
+        }
+
+        code.insert(pc, (instr, size));
+    }
+
+    Function { entry, code }
+}
+
+fn value((mode, arg): (ParamMode, i64)) -> String {
+    match mode {
+        ParamMode::Position => format!("m[{}]", arg),
+        ParamMode::Immediate => format!("{}", arg),
+        ParamMode::Relative if arg == 0 => "m[rb]".to_string(),
+        ParamMode::Relative => format!("m[rb{:+}]", arg),
+    }
+}
+
+fn sum(lhs: (ParamMode, i64), rhs: (ParamMode, i64)) -> String {
+    match (lhs, rhs) {
+        (x, (ParamMode::Immediate, 0)) | ((ParamMode::Immediate, 0), x) => value(x),
+        (x, (ParamMode::Immediate, n)) if n < 0 => format!("{} - {}", value(x), -n),
+        _ => format!("{} + {}", value(lhs), value(rhs)),
+    }
+}
+
+fn product(lhs: (ParamMode, i64), rhs: (ParamMode, i64)) -> String {
+    match (lhs, rhs) {
+        (x, (ParamMode::Immediate, 1)) | ((ParamMode::Immediate, 1), x) => value(x),
+        _ => format!("{} * {}", value(lhs), value(rhs)),
+    }
+}
+
+// The condition under which a conditional jump is taken, or its negation
+fn condition(opcode: &Opcode, taken: bool) -> String {
+    let test = value(opcode.params[0]);
+    if (opcode.root == OpcodeRoot::JumpTrue) == taken {
+        format!("{} != 0", test)
+    } else {
+        format!("{} == 0", test)
+    }
+}
+
+fn name(entry: usize) -> String {
+    if entry == 0 {
+        "main".to_string()
+    } else {
+        format!("f{}", entry)
+    }
+}
+
+fn statement(opcode: &Opcode) -> String {
+    let p = &opcode.params;
+
+    match opcode.root {
+        OpcodeRoot::Add => format!("{} = {};", value(p[2]), sum(p[0], p[1])),
+        OpcodeRoot::Multiply => format!("{} = {};", value(p[2]), product(p[0], p[1])),
+        OpcodeRoot::LessThan => format!("{} = {} < {};", value(p[2]), value(p[0]), value(p[1])),
+        OpcodeRoot::Equals => format!("{} = {} == {};", value(p[2]), value(p[0]), value(p[1])),
+        OpcodeRoot::Input => format!("{} = input();", value(p[0])),
+        OpcodeRoot::Output => format!("output({});", value(p[0])),
+        OpcodeRoot::SetBase => match p[0] {
+            (ParamMode::Immediate, n) if n < 0 => format!("rb -= {};", -n),
+            x => format!("rb += {};", value(x)),
+        },
+        OpcodeRoot::Halt => "halt();".to_string(),
+        OpcodeRoot::JumpTrue | OpcodeRoot::JumpFalse => {
+            let target = match immediate_target(opcode) {
+                Some(t) => format!("L{}", t),
+                None => format!("*{}", value(p[1])),
+            };
+            if always_taken(opcode) {
+                format!("goto {};", target)
+            } else {
+                format!("if ({}) goto {};", condition(opcode, true), target)
+            }
+        }
+    }
+}
+
+struct Emitter<'a> {
+    func: &'a Function,
+    // (address, nesting depth, text), where closing lines have no address
+    lines: Vec<(Option<usize>, usize, String)>,
+    gotos: BTreeSet<usize>,
+}
+
+impl<'a> Emitter<'a> {
+    fn plain(&self, pc: usize) -> Option<&'a Opcode> {
+        match self.func.code.get(&pc) {
+            Some((Instr::Plain(opcode), _)) => Some(opcode),
+            _ => None,
+        }
+    }
+
+    // The furthest jump in [pc, end) that goes back to `pc`
+    fn loop_end(&self, pc: usize, end: usize) -> Option<usize> {
+        self.func.code.range(pc..end).rev().find_map(|(&at, (instr, _))| match instr {
+            Instr::Plain(opcode) if is_jump(opcode) && immediate_target(opcode) == Some(pc) => Some(at),
+            _ => None,
+        })
+    }
+
+    fn line(&mut self, pc: usize, depth: usize, text: String) {
+        self.lines.push((Some(pc), depth, text));
+    }
+
+    fn close(&mut self, depth: usize, text: String) {
+        self.lines.push((None, depth, text));
+    }
+
+    fn emit_range(&mut self, start: usize, end: usize, depth: usize) {
+        let mut next = self.func.code.range(start..end).next().map(|(&pc, _)| pc);
+
+        while let Some(pc) = next {
+            let after = self.emit_at(pc, end, depth);
+            if after >= end {
+                break;
+            }
+            next = self.func.code.range(after..end).next().map(|(&pc, _)| pc);
+        }
+    }
+
+    // Emits whatever construct starts at `pc`, returning the address after it
+    fn emit_at(&mut self, pc: usize, end: usize, depth: usize) -> usize {
+        let size = self.func.code[&pc].1;
+
+        if let Some(back) = self.loop_end(pc, end) {
+            let jump = self.plain(back).unwrap();
+            let jump_size = jump.size();
+
+            if always_taken(jump) {
+                self.line(pc, depth, "loop {".to_string());
+                self.emit_range(pc, back, depth + 1);
+                self.close(depth, "}".to_string());
+            } else {
+                self.line(pc, depth, "do {".to_string());
+                self.emit_range(pc, back, depth + 1);
+                self.close(depth, format!("}} while ({});", condition(jump, true)));
+            }
+
+            return back + jump_size;
+        }
+
+        let func = self.func;
+
+        let text = match &func.code[&pc].0 {
+            Instr::Call { target, .. } => format!("{}();", name(*target)),
+            Instr::Return => "return;".to_string(),
+            Instr::Invalid(word) => format!("/* invalid instruction {} */", word),
+            Instr::Plain(opcode) if is_jump(opcode) => {
+                let structured = immediate_target(opcode).filter(|&t| t > pc + size && t <= end && !always_taken(opcode));
+
+                if let Some(target) = structured {
+                    return self.emit_if(pc, opcode, target, end, depth);
+                }
+
+                self.gotos.extend(immediate_target(opcode));
+                statement(opcode)
+            }
+            Instr::Plain(opcode) => statement(opcode),
+        };
+
+        self.line(pc, depth, text);
+        pc + size
+    }
+
+    // A conditional jump forward over a block is an if, and if that block
+    // ends by jumping over a second block, the second block is its else
+    fn emit_if(&mut self, pc: usize, opcode: &Opcode, target: usize, end: usize, depth: usize) -> usize {
+        let body = pc + opcode.size();
+
+        let last = self.func.code.range(body..target).next_back().map(|(&at, _)| at);
+        let otherwise = last.and_then(|at| {
+            let jump = self.plain(at).filter(|j| is_jump(j) && always_taken(j))?;
+            let over = immediate_target(jump).filter(|&m| m > target && m <= end)?;
+            Some((at, over))
+        });
+
+        self.line(pc, depth, format!("if ({}) {{", condition(opcode, false)));
+
+        match otherwise {
+            Some((at, over)) => {
+                self.emit_range(body, at, depth + 1);
+                self.close(depth, "} else {".to_string());
+                self.emit_range(target, over, depth + 1);
+                self.close(depth, "}".to_string());
+                over
+            }
+            None => {
+                self.emit_range(body, target, depth + 1);
+                self.close(depth, "}".to_string());
+                target
+            }
+        }
+    }
+}
+
+fn render(func: &Function, out: &mut String) {
+    let mut emitter = Emitter { func, lines: Vec::new(), gotos: BTreeSet::new() };
+
+    let end = func.code.iter().next_back().map(|(&pc, (_, size))| pc + size).unwrap_or(func.entry);
+    emitter.emit_range(func.entry, end, 1);
+
+    // Anything before the entry (reached by jumping backwards out of it) goes last
+    emitter.emit_range(0, func.entry, 1);
+
+    writeln!(out, "fn {}() {{", name(func.entry)).unwrap();
+
+    let mut labelled = BTreeSet::new();
+    for (pc, depth, text) in emitter.lines.iter() {
+        if let Some(pc) = pc.filter(|pc| emitter.gotos.contains(pc) && labelled.insert(*pc)) {
+            writeln!(out, "L{}:", pc).unwrap();
+        }
+        writeln!(out, "{}{}", "    ".repeat(*depth), text).unwrap();
+    }
+
+    writeln!(out, "}}").unwrap();
+}
+
+// Recovers functions (using the `[rb+0] = return address; jump` calling
+// convention), loops and if/else from a program and prints them as C-like pseudocode
+pub fn decompile(program: &[i64]) -> String {
+    let mut entries = BTreeSet::new();
+    entries.insert(0);
+
+    let mut functions = BTreeMap::new();
+
+    while let Some(&entry) = entries.iter().find(|e| !functions.contains_key(*e)) {
+        let func = walk(program, entry, &mut entries);
+        functions.insert(entry, func);
+    }
+
+    let mut result = String::new();
+
+    for func in functions.values() {
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        render(func, &mut result);
+    }
+
+    result
+}
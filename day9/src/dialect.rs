@@ -0,0 +1,128 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{disasm, pad_memory, step_prog, Opcode, OpcodeRoot, ParamMode, ProgramState};
+use crate::extension::Extensions;
+
+// The instruction set a program was written against. Each one is a strict
+// superset of the one before, so newer programs are rejected by older dialects.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dialect {
+    // Add, multiply and halt, with position mode only
+    Day2,
+    // Adds I/O, jumps, comparisons and immediate mode
+    Day5,
+    // Adds the relative base and relative mode
+    #[default]
+    Day9,
+}
+
+impl Dialect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::Day2 => "day2",
+            Dialect::Day5 => "day5",
+            Dialect::Day9 => "day9",
+        }
+    }
+
+    // The oldest dialect an opcode is legal in
+    fn introducing_root(root: OpcodeRoot) -> Dialect {
+        match root {
+            OpcodeRoot::Add | OpcodeRoot::Multiply | OpcodeRoot::Halt => Dialect::Day2,
+            OpcodeRoot::SetBase => Dialect::Day9,
+            _ => Dialect::Day5,
+        }
+    }
+
+    fn introducing_mode(mode: ParamMode) -> Dialect {
+        match mode {
+            ParamMode::Position => Dialect::Day2,
+            ParamMode::Immediate => Dialect::Day5,
+            ParamMode::Relative => Dialect::Day9,
+        }
+    }
+
+    pub fn check(self, opcode: &Opcode, pc: usize) -> Result<(), DialectError> {
+        let needs = Dialect::introducing_root(opcode.root);
+        if needs > self {
+            return Err(DialectError { pc, dialect: self, needs, feature: Feature::Opcode(opcode.root) });
+        }
+
+        for (idx, &(mode, _)) in opcode.params.iter().enumerate() {
+            let needs = Dialect::introducing_mode(mode);
+            if needs > self {
+                return Err(DialectError { pc, dialect: self, needs, feature: Feature::Mode(idx, mode) });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Dialect, String> {
+        match text {
+            "day2" => Ok(Dialect::Day2),
+            "day5" | "day7" => Ok(Dialect::Day5),
+            "day9" => Ok(Dialect::Day9),
+            _ => Err(format!("Unknown dialect {:?}, expected day2, day5 or day9", text)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Feature {
+    Opcode(OpcodeRoot),
+    // A parameter index and the mode it used
+    Mode(usize, ParamMode),
+}
+
+// An instruction that uses something its program's dialect doesn't have
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialectError {
+    pub pc: usize,
+    pub dialect: Dialect,
+    pub needs: Dialect,
+    pub feature: Feature,
+}
+
+impl fmt::Display for DialectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.feature {
+            Feature::Opcode(root) => write!(
+                f, "Opcode {} ({}) at {} needs {}, but the program is {}",
+                *root as i64, disasm::mnemonic(*root), self.pc, self.needs.name(), self.dialect.name(),
+            ),
+            Feature::Mode(idx, mode) => write!(
+                f, "{:?} mode on parameter {} at {} needs {}, but the program is {}",
+                mode, idx + 1, self.pc, self.needs.name(), self.dialect.name(),
+            ),
+        }
+    }
+}
+
+// Runs a program under `dialect`, with custom opcodes on top of whatever it
+// allows, stopping at the first instruction it doesn't allow
+pub fn run_prog_in(mut program: Vec<i64>, input: &[i64], dialect: Dialect, extensions: &Extensions) -> Result<Vec<i64>, DialectError> {
+    pad_memory(&mut program);
+
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: extensions.clone(), dialect };
+    let mut output = Vec::new();
+
+    loop {
+        if let Some(opcode) = Opcode::try_parse(&state.program[state.pc..]) {
+            dialect.check(&opcode, state.pc)?;
+        }
+
+        let (out, halted) = step_prog(&mut state);
+
+        output.extend(out);
+
+        if halted {
+            return Ok(output);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use proptest::prelude::*;
+
+use super::*;
+
+const MAX_STEPS: usize = 1000;
+
+// The plain backend: like run_prog, but gives up after `max_steps` instructions
+fn run_prog_limited(mut program: Vec<i64>, input: &[i64], max_steps: usize) -> RunResult {
+    pad_memory(&mut program);
+
+    let mut output = Vec::new();
+
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+
+    let halted = run_state(&mut state, &mut output, max_steps).unwrap_or_else(|fault| panic!("{}", fault));
+
+    RunResult { output, memory: state.program, halted }
+}
+
+fn instruction() -> impl Strategy<Value = Vec<i64>> {
+    (1..=9i64, prop::array::uniform3(0..3i64), prop::array::uniform3(-4..64i64)).prop_map(|(op, mut modes, args)| {
+        let root = OpcodeRoot::from_i64(op).unwrap();
+
+        // Keep writes out of immediate mode so most programs do something useful
+        if let Some(w) = root.write_param() {
+            if modes[w] == 1 {
+                modes[w] = 0;
+            }
+        }
+
+        let count = root.arg_count();
+        let code = op + (0..count).map(|i| modes[i] * 10_i64.pow(i as u32 + 2)).sum::<i64>();
+
+        std::iter::once(code).chain(args[..count].iter().copied()).collect()
+    })
+}
+
+fn program() -> impl Strategy<Value = Vec<i64>> {
+    (prop::collection::vec(instruction(), 1..24), prop::collection::vec(-100..100i64, 0..16)).prop_map(|(code, data)| {
+        let mut program = code.concat();
+        program.push(99);
+        program.extend(data);
+        program
+    })
+}
+
+// None if the backend panicked, which both backends should agree on
+fn outcome(run: fn(Vec<i64>, &[i64], usize) -> RunResult, program: &[i64], input: &[i64]) -> Option<RunResult> {
+    panic::catch_unwind(AssertUnwindSafe(|| run(program.to_vec(), input, MAX_STEPS))).ok()
+}
+
+proptest! {
+    #[test]
+    fn cached_matches_plain(program in program(), input in prop::collection::vec(any::<i32>().prop_map(i64::from), 0..8)) {
+        let plain = outcome(run_prog_limited, &program, &input);
+        let cached = outcome(cached::run_prog_limited, &program, &input);
+
+        prop_assert_eq!(plain, cached);
+    }
+
+    #[test]
+    fn optimizer_preserves_behaviour(program in program(), input in prop::collection::vec(-100..100i64, 0..8)) {
+        let before = match outcome(run_prog_limited, &program, &input) {
+            Some(result) if result.halted => result,
+            _ => return Ok(()),
+        };
+
+        let optimized = optimize::optimize(&program, &optimize::OptimizeOptions::default());
+        let after = outcome(run_prog_limited, &optimized, &input);
+
+        prop_assert!(optimized.len() <= program.len());
+        prop_assert_eq!(Some((before.output, true)), after.map(|r| (r.output, r.halted)));
+    }
+}
+
+#[test]
+fn cached_sees_self_modification() {
+    // Outputs 5, then patches the output's operand and loops back to it
+    let prog = vec![104, 5, 1101, 0, 7, 1, 1105, 1, 0];
+    let plain = run_prog_limited(prog.clone(), &[], 7);
+    let cached = cached::run_prog_limited(prog, &[], 7);
+    assert_eq!(plain.output, vec![5, 7, 7]);
+    assert_eq!(plain, cached);
+}
+
+#[test]
+fn cached_runs_boost() {
+    let boost = parse_input(Path::new("./input.txt"));
+    let plain = run_prog_limited(boost.clone(), &[2], usize::MAX);
+    let cached = cached::run_prog_limited(boost, &[2], usize::MAX);
+    assert_eq!(cached.output, vec![60962]);
+    assert_eq!(plain, cached);
+}
@@ -0,0 +1,57 @@
+use std::fmt::Write;
+
+use crate::{Opcode, OpcodeRoot, ParamMode};
+
+pub fn mnemonic(root: OpcodeRoot) -> &'static str {
+    match root {
+        OpcodeRoot::Add => "add",
+        OpcodeRoot::Multiply => "mul",
+        OpcodeRoot::Input => "in",
+        OpcodeRoot::Output => "out",
+        OpcodeRoot::JumpTrue => "jt",
+        OpcodeRoot::JumpFalse => "jf",
+        OpcodeRoot::LessThan => "lt",
+        OpcodeRoot::Equals => "eq",
+        OpcodeRoot::SetBase => "arb",
+        OpcodeRoot::Halt => "hlt",
+    }
+}
+
+pub fn operand((mode, arg): (ParamMode, i64)) -> String {
+    match mode {
+        ParamMode::Position => format!("[{}]", arg),
+        ParamMode::Immediate => format!("{}", arg),
+        ParamMode::Relative => format!("[rb{:+}]", arg),
+    }
+}
+
+pub fn instruction(opcode: &Opcode) -> String {
+    let args = opcode.params.iter().map(|p| operand(*p)).collect::<Vec<_>>();
+
+    if args.is_empty() {
+        mnemonic(opcode.root).to_string()
+    } else {
+        format!("{} {}", mnemonic(opcode.root), args.join(", "))
+    }
+}
+
+// A linear sweep: anything that doesn't decode is printed as a data word
+pub fn disassemble(program: &[i64]) -> String {
+    let mut result = String::new();
+    let mut pc = 0;
+
+    while pc < program.len() {
+        match Opcode::try_parse(&program[pc..]) {
+            Some(opcode) => {
+                writeln!(result, "{:>6}: {}", pc, instruction(&opcode)).unwrap();
+                pc += opcode.size();
+            }
+            None => {
+                writeln!(result, "{:>6}: data {}", pc, program[pc]).unwrap();
+                pc += 1;
+            }
+        }
+    }
+
+    result
+}
@@ -0,0 +1,97 @@
+use std::fmt::Write as _;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpStream;
+
+use crate::{disasm, pad_memory, step_access, step_prog, OpcodeRoot, ProgramState};
+use crate::dialect::Dialect;
+use crate::extension::Extensions;
+use num_traits::FromPrimitive;
+
+// Writes one JSON object per executed step, so tools in other languages
+// can follow a run without linking against the VM
+pub struct EventStream<W: Write> {
+    out: W,
+    step: u64,
+}
+
+fn pairs(addrs: &[usize], memory: &[i64]) -> String {
+    let items = addrs.iter().map(|&a| format!("[{},{}]", a, memory[a])).collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+impl<W: Write> EventStream<W> {
+    pub fn new(out: W) -> EventStream<W> {
+        EventStream { out, step: 0 }
+    }
+
+    // Steps the machine once and records what happened
+    pub fn step(&mut self, state: &mut ProgramState) -> io::Result<(Option<i64>, bool)> {
+        let access = step_access(state);
+        let code = state.program[state.pc];
+        let base = state.relative_base;
+        let input_idx = state.input_idx;
+        let reads = pairs(&access.reads, &state.program);
+
+        let (out, halted) = step_prog(state);
+
+        let op = match OpcodeRoot::from_i64(code % 100) {
+            Some(root) if state.extensions.get((code % 100) as u32).is_none() => disasm::mnemonic(root).to_string(),
+            _ => format!("ext{}", code % 100),
+        };
+
+        let mut line = String::new();
+        write!(
+            line,
+            r#"{{"step":{},"pc":{},"opcode":{},"op":"{}","relative_base":{},"reads":{},"writes":{},"input":{:?},"output":{},"halted":{}}}"#,
+            self.step, access.pc, code, op, base, reads,
+            pairs(&access.writes, &state.program),
+            &state.input[input_idx..state.input_idx],
+            out.map_or("null".to_string(), |o| o.to_string()),
+            halted,
+        ).unwrap();
+
+        writeln!(self.out, "{}", line)?;
+        self.step += 1;
+
+        Ok((out, halted))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// A file path, or `tcp:host:port`, or `unix:/path/to/socket`
+pub fn open_sink(target: &str) -> io::Result<Box<dyn Write>> {
+    if let Some(addr) = target.strip_prefix("tcp:") {
+        return Ok(Box::new(BufWriter::new(TcpStream::connect(addr)?)));
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = target.strip_prefix("unix:") {
+            return Ok(Box::new(BufWriter::new(std::os::unix::net::UnixStream::connect(path)?)));
+        }
+    }
+
+    Ok(Box::new(BufWriter::new(std::fs::File::create(target)?)))
+}
+
+pub fn run_traced<W: Write>(mut program: Vec<i64>, input: &[i64], out: W) -> io::Result<Vec<i64>> {
+    pad_memory(&mut program);
+
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+    let mut events = EventStream::new(out);
+    let mut output = Vec::new();
+
+    loop {
+        let (out, halted) = events.step(&mut state)?;
+
+        output.extend(out);
+
+        if halted {
+            events.flush()?;
+            return Ok(output);
+        }
+    }
+}
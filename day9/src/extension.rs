@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use num_traits::FromPrimitive;
+
+use crate::{OpcodeRoot, ParamMode};
+
+// Everything a custom opcode handler can see and change while it runs.
+// Read parameters are already resolved to values, write parameters are
+// stored back into memory once the handler returns.
+pub struct ExtCall<'a> {
+    pub memory: &'a mut [i64],
+    pub relative_base: &'a mut i64,
+    args: Vec<i64>,
+    writes: &'a [usize],
+    input: &'a [i64],
+    inputs: usize,
+    consumed: usize,
+    output: Option<i64>,
+    jump: Option<usize>,
+}
+
+impl<'a> ExtCall<'a> {
+    pub fn arg(&self, idx: usize) -> i64 {
+        assert!(!self.writes.contains(&idx), "Parameter {} is a write parameter", idx);
+        self.args[idx]
+    }
+
+    pub fn set(&mut self, idx: usize, value: i64) {
+        assert!(self.writes.contains(&idx), "Parameter {} is not a write parameter", idx);
+        self.args[idx] = value;
+    }
+
+    pub fn input(&mut self) -> i64 {
+        assert!(self.consumed < self.inputs, "Opcode reads more input than the {} it declared", self.inputs);
+        let value = self.input[self.consumed];
+        self.consumed += 1;
+        value
+    }
+
+    pub fn output(&mut self, value: i64) {
+        assert!(self.output.is_none(), "Opcodes can only output one value per step");
+        self.output = Some(value);
+    }
+
+    pub fn jump(&mut self, target: usize) {
+        self.jump = Some(target);
+    }
+}
+
+type Handler = dyn Fn(&mut ExtCall) + Send + Sync;
+
+pub struct Extension {
+    pub arg_count: usize,
+    pub writes: Vec<usize>,
+    // The most input values one step reads
+    pub inputs: usize,
+    handler: Box<Handler>,
+}
+
+// A set of custom opcodes, cheap to clone so every machine in a network can share it.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    ops: HashMap<u32, Arc<Extension>>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        Extensions::default()
+    }
+
+    pub fn register<F>(&mut self, code: u32, arg_count: usize, writes: &[usize], handler: F)
+        where F: Fn(&mut ExtCall) + Send + Sync + 'static
+    {
+        self.register_input(code, arg_count, writes, 0, handler);
+    }
+
+    // Like `register`, for opcodes that read up to `inputs` values per step.
+    // Machines wait for that many before running one.
+    pub fn register_input<F>(&mut self, code: u32, arg_count: usize, writes: &[usize], inputs: usize, handler: F)
+        where F: Fn(&mut ExtCall) + Send + Sync + 'static
+    {
+        assert!(code < 100, "Opcode {} does not fit in two digits", code);
+        assert!(OpcodeRoot::from_u32(code).is_none(), "Opcode {} is already a builtin", code);
+        assert!(!self.ops.contains_key(&code), "Opcode {} is already registered", code);
+        assert!(writes.iter().all(|&w| w < arg_count), "Write parameter out of range for opcode {}", code);
+
+        let ext = Extension {
+            arg_count,
+            writes: writes.to_vec(),
+            inputs,
+            handler: Box::new(handler),
+        };
+
+        self.ops.insert(code, Arc::new(ext));
+    }
+
+    pub fn get(&self, code: u32) -> Option<&Extension> {
+        self.ops.get(&code).map(|e| &**e)
+    }
+}
+
+impl Extension {
+    pub fn params(&self, program: &[i64], pc: usize) -> Vec<(ParamMode, i64)> {
+        (0..self.arg_count).map(|i| {
+            let digit = (program[pc] / 10_i64.pow(i as u32 + 2)) % 10;
+            let mode = ParamMode::from_i64(digit).unwrap_or_else(|| panic!("Invalid mode {}", digit));
+            (mode, program[pc + 1 + i])
+        }).collect()
+    }
+
+    // Runs the extension whose instruction starts at `pc`,
+    // returning (jump target, output, inputs consumed)
+    pub fn execute(&self, program: &mut [i64], pc: usize, input: &[i64], relative_base: &mut i64) -> (Option<usize>, Option<i64>, usize) {
+        let base = *relative_base;
+
+        let params = self.params(program, pc);
+
+        let args = params.iter().enumerate().map(|(i, &(mode, arg))| {
+            if self.writes.contains(&i) {
+                0
+            } else {
+                crate::Opcode::read(mode, arg, program, base)
+            }
+        }).collect();
+
+        let mut call = ExtCall {
+            memory: program,
+            relative_base,
+            args,
+            writes: &self.writes,
+            input,
+            inputs: self.inputs,
+            consumed: 0,
+            output: None,
+            jump: None,
+        };
+
+        (self.handler)(&mut call);
+
+        let ExtCall { args, consumed, output, jump, .. } = call;
+
+        for &w in self.writes.iter() {
+            let (mode, arg) = params[w];
+            crate::Opcode::write(args[w], mode, arg, program, base);
+        }
+
+        (jump, output, consumed)
+    }
+}
+
+fn stack_top(call: &ExtCall) -> usize {
+    usize::try_from(*call.relative_base - 1).unwrap_or_else(|_| panic!("Stack underflow at relative base {}", call.relative_base))
+}
+
+// The opcodes `run ... ext` adds: integer division, which Intcode lacks, and
+// a stack kept at the relative base, which programs otherwise build by hand.
+// Dividing by zero faults like any other bad instruction.
+//
+//   10 div a b -> c   c = a / b
+//   11 mod a b -> c   c = a % b
+//   12 push a         memory[rb] = a, then rb += 1
+//   13 pop -> a       rb -= 1, then a = memory[rb]
+//   14 ret            pop an address and jump to it
+//   15 echo           output the next input unchanged
+pub fn stdlib() -> Extensions {
+    let mut ext = Extensions::new();
+
+    ext.register(10, 3, &[2], |c| {
+        let value = c.arg(0).checked_div(c.arg(1)).unwrap_or_else(|| panic!("Cannot divide {} by {}", c.arg(0), c.arg(1)));
+        c.set(2, value);
+    });
+    ext.register(11, 3, &[2], |c| {
+        let value = c.arg(0).checked_rem(c.arg(1)).unwrap_or_else(|| panic!("Cannot divide {} by {}", c.arg(0), c.arg(1)));
+        c.set(2, value);
+    });
+    ext.register(12, 1, &[], |c| {
+        let top = usize::try_from(*c.relative_base).unwrap_or_else(|_| panic!("Negative relative base {}", c.relative_base));
+        c.memory[top] = c.arg(0);
+        *c.relative_base += 1;
+    });
+    ext.register(13, 1, &[0], |c| {
+        let top = stack_top(c);
+        c.set(0, c.memory[top]);
+        *c.relative_base -= 1;
+    });
+    ext.register(14, 0, &[], |c| {
+        let top = stack_top(c);
+        let target = c.memory[top] as usize;
+        *c.relative_base -= 1;
+        c.jump(target);
+    });
+    ext.register_input(15, 0, &[], 1, |c| {
+        let value = c.input();
+        c.output(value);
+    });
+
+    ext
+}
@@ -0,0 +1,246 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Program files are text or binary.
+//
+// Text files hold integers separated by commas, whitespace or newlines, in
+// any mix. `#` starts a comment that runs to the end of the line. Lines
+// starting with `#!` before the first value are metadata:
+//
+//   #! name: BOOST
+//   #! input: 1
+//   #! output: 4234906522
+//   109,1,204,-1
+//
+// Every `input` starts a new self-check, and the `output` after it is what
+// the program should print when given that input.
+//
+// Binary files start with MAGIC, then the metadata lines as length-prefixed
+// text, then the values, all as zigzag LEB128 varints.
+pub const MAGIC: &[u8] = b"\0icb";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Check {
+    pub input: Vec<i64>,
+    pub output: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgramFile {
+    pub name: Option<String>,
+    pub checks: Vec<Check>,
+    pub code: Vec<i64>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    // 1-based line and column in a text file
+    Text { line: usize, column: usize, message: String },
+    Binary { offset: usize, message: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Text { line, column, message } => write!(f, "{}:{}: {}", line, column, message),
+            LoadError::Binary { offset, message } => write!(f, "byte {}: {}", offset, message),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError {
+        LoadError::Io(e)
+    }
+}
+
+fn text_error(line: usize, column: usize, message: String) -> LoadError {
+    LoadError::Text { line, column, message }
+}
+
+// The integers in `text`, which starts at `column` of `line`
+fn values(text: &str, line: usize, column: usize) -> Result<Vec<i64>, LoadError> {
+    let mut result = Vec::new();
+    let mut start = None;
+
+    for (idx, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        let separator = c == ',' || c.is_whitespace();
+
+        match (start, separator) {
+            (None, false) => start = Some(idx),
+            (Some(s), true) => {
+                let word = &text[s..idx];
+                let value = word.parse::<i64>().map_err(|_| text_error(line, column + s, format!("Invalid value {:?}", word)))?;
+                result.push(value);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+// One `key: value` metadata line, where `column` is where `text` starts
+fn metadata(file: &mut ProgramFile, text: &str, line: usize, column: usize) -> Result<(), LoadError> {
+    let colon = text.find(':').ok_or_else(|| text_error(line, column, "Expected `key: value` metadata".to_string()))?;
+    let key = text[..colon].trim();
+    let value = &text[colon + 1..];
+    let value_column = column + colon + 1;
+
+    match key {
+        "name" => file.name = Some(value.trim().to_string()),
+        "input" => file.checks.push(Check { input: values(value, line, value_column)?, output: Vec::new() }),
+        "output" => match file.checks.last_mut() {
+            Some(check) => check.output = values(value, line, value_column)?,
+            None => return Err(text_error(line, column, "`output` needs an `input` before it".to_string())),
+        },
+        _ => {
+            let offset = text.len() - text.trim_start().len();
+            return Err(text_error(line, column + offset, format!("Unknown metadata key {:?}", key)));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn parse_text(text: &str) -> Result<ProgramFile, LoadError> {
+    let mut file = ProgramFile::default();
+
+    for (idx, line) in text.lines().enumerate() {
+        let number = idx + 1;
+
+        if let Some(rest) = line.strip_prefix("#!") {
+            if !file.code.is_empty() {
+                return Err(text_error(number, 1, "Metadata must come before the program".to_string()));
+            }
+            metadata(&mut file, rest, number, 3)?;
+            continue;
+        }
+
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+
+        file.code.extend(values(code, number, 1)?);
+    }
+
+    Ok(file)
+}
+
+// The `#!` lines that describe `file`, without the prefix
+fn header(file: &ProgramFile) -> String {
+    let join = |values: &[i64]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+
+    let mut result = String::new();
+    if let Some(name) = &file.name {
+        result += &format!("name: {}\n", name);
+    }
+    for check in file.checks.iter() {
+        result += &format!("input: {}\noutput: {}\n", join(&check.input), join(&check.output));
+    }
+
+    result
+}
+
+fn write_varint(out: &mut Vec<u8>, value: i64) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+
+    loop {
+        let byte = (zigzag & 0x7f) as u8;
+        zigzag >>= 7;
+
+        if zigzag == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<i64, LoadError> {
+    let start = *pos;
+    let mut zigzag = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or_else(|| LoadError::Binary { offset: start, message: "Truncated value".to_string() })?;
+        *pos += 1;
+
+        // The last byte only has room for the top bit
+        if shift == 63 && byte & 0x7e != 0 {
+            return Err(LoadError::Binary { offset: start, message: "Value longer than 64 bits".to_string() });
+        }
+        zigzag |= ((byte & 0x7f) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64));
+        }
+    }
+
+    Err(LoadError::Binary { offset: start, message: "Value longer than 64 bits".to_string() })
+}
+
+pub fn encode_binary(file: &ProgramFile) -> Vec<u8> {
+    let header = header(file);
+
+    let mut out = MAGIC.to_vec();
+    write_varint(&mut out, header.len() as i64);
+    out.extend_from_slice(header.as_bytes());
+
+    for &value in file.code.iter() {
+        write_varint(&mut out, value);
+    }
+
+    out
+}
+
+pub fn parse_binary(data: &[u8]) -> Result<ProgramFile, LoadError> {
+    if !data.starts_with(MAGIC) {
+        return Err(LoadError::Binary { offset: 0, message: "Not a binary program".to_string() });
+    }
+
+    let mut pos = MAGIC.len();
+    let header_len = read_varint(data, &mut pos)?;
+    let header_len = usize::try_from(header_len)
+        .map_err(|_| LoadError::Binary { offset: MAGIC.len(), message: format!("Negative metadata length {}", header_len) })?;
+    let header = pos.checked_add(header_len)
+        .and_then(|end| data.get(pos..end))
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| LoadError::Binary { offset: pos, message: "Bad metadata".to_string() })?;
+
+    let mut file = ProgramFile::default();
+    for (idx, line) in header.lines().enumerate() {
+        metadata(&mut file, line, idx + 1, 1).map_err(|e| LoadError::Binary { offset: pos, message: format!("Bad metadata: {}", e) })?;
+    }
+    pos += header_len;
+
+    while pos < data.len() {
+        file.code.push(read_varint(data, &mut pos)?);
+    }
+
+    Ok(file)
+}
+
+pub fn parse(data: &[u8]) -> Result<ProgramFile, LoadError> {
+    if data.starts_with(MAGIC) {
+        return parse_binary(data);
+    }
+
+    let text = std::str::from_utf8(data).map_err(|e| {
+        let before = &data[..e.valid_up_to()];
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&b| b != b'\n').count() + 1;
+        text_error(line, column, "Invalid UTF-8".to_string())
+    })?;
+
+    parse_text(text)
+}
+
+pub fn load(path: &Path) -> Result<ProgramFile, LoadError> {
+    parse(&std::fs::read(path)?)
+}
@@ -0,0 +1,80 @@
+use futures::channel::mpsc;
+use futures::future;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+
+use crate::{needs_input, step_prog, ProgramState};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Exit {
+    Halted,
+    // Waiting for input, but the input stream ended
+    InputClosed,
+    // Had output to send, but nobody is listening any more
+    OutputClosed,
+}
+
+// Runs a machine that pulls its input from a stream and pushes its output
+// into a sink. Only waiting on either of those yields to the executor, so
+// machines wired together with channels can share a single thread.
+pub async fn run_async<I, O>(state: &mut ProgramState, mut input: I, mut output: O) -> Exit
+    where I: Stream<Item = i64> + Unpin, O: Sink<i64> + Unpin
+{
+    loop {
+        while needs_input(state) {
+            match input.next().await {
+                Some(value) => state.input.push(value),
+                None => return Exit::InputClosed,
+            }
+        }
+
+        let (out, halted) = step_prog(state);
+
+        if let Some(out) = out {
+            if output.send(out).await.is_err() {
+                return Exit::OutputClosed;
+            }
+        }
+
+        if halted {
+            return Exit::Halted;
+        }
+    }
+}
+
+// Wires the machines into a loop, each one's output feeding the next one's
+// input and the last one's feeding back into the first. `seed` is sent to
+// the first machine to get things going. Returns the last machine's final output.
+pub async fn run_ring(machines: &mut [ProgramState], seed: i64) -> Option<i64> {
+    if machines.is_empty() {
+        return None;
+    }
+
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..=machines.len()).map(|_| mpsc::unbounded::<i64>()).unzip();
+    let mut receivers = receivers.into_iter();
+
+    let feedback = receivers.next_back().unwrap();
+    let first = senders[0].clone();
+    first.unbounded_send(seed).unwrap();
+
+    let runs = machines.iter_mut()
+        .zip(receivers)
+        .zip(senders.into_iter().skip(1))
+        .map(|((state, input), output)| run_async(state, input, output));
+
+    // Passes values from the last machine back to the first, remembering the latest
+    let forward = async move {
+        let mut feedback = feedback;
+        let mut last = None;
+
+        while let Some(value) = feedback.next().await {
+            last = Some(value);
+            let _ = first.unbounded_send(value);
+        }
+
+        last
+    };
+
+    let (_, last) = future::join(future::join_all(runs), forward).await;
+
+    last
+}
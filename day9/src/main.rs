@@ -1,7 +1,5 @@
-use std::time::Instant;
 use std::convert::TryFrom;
 use num_traits::FromPrimitive;
-use std::io::{self, prelude::*};
 use std::path::Path;
 use std::slice::Iter as SliceIter;
 
@@ -9,10 +7,39 @@ extern crate num_traits;
 #[macro_use]
 extern crate num_derive;
 
+mod cached;
+mod coverage;
+mod decompile;
+mod dialect;
+mod disasm;
+mod events;
+mod extension;
+mod loader;
+mod machine;
+mod optimize;
+mod pool;
+mod remote;
+mod selftest;
+mod sweep;
+mod watchdog;
+#[cfg(test)]
+mod differential;
+#[cfg(test)]
+mod tests;
+
+use dialect::Dialect;
+use extension::Extensions;
+use pool::{Fault, Pool};
+use sweep::Sweep;
+
 fn parse_input(path: &std::path::Path) -> Vec<i64> {
-    std::fs::read_to_string(path)
-        .unwrap()
-        .split(',')
+    loader::load(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)).code
+}
+
+// Comma-separated values from the command line, where an empty string is no values
+fn parse_values(text: &str) -> Vec<i64> {
+    text.split(',')
+        .filter(|n| !n.trim().is_empty())
         .map(|n| n.trim().parse::<i64>().unwrap_or_else(|_| panic!("{}", n)))
         .collect()
 }
@@ -53,9 +80,18 @@ impl OpcodeRoot {
             OpcodeRoot::Halt => 0,
         }
     }
+
+    // Which parameter, if any, is stored to
+    pub fn write_param(&self) -> Option<usize> {
+        match self {
+            OpcodeRoot::Add | OpcodeRoot::Multiply | OpcodeRoot::LessThan | OpcodeRoot::Equals => Some(2),
+            OpcodeRoot::Input => Some(0),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Opcode {
     pub root: OpcodeRoot,
     pub params: Vec<(ParamMode, i64)>,
@@ -99,6 +135,32 @@ impl Opcode {
         }
     }
 
+    // Like parse, but returns None instead of panicking on anything that isn't an instruction
+    pub fn try_parse(data: &[i64]) -> Option<Opcode> {
+        let code = u32::try_from(*data.first()?).ok()?;
+        let root = OpcodeRoot::from_u32(code % 100)?;
+
+        let params = (0..root.arg_count()).map(|i| {
+            let mode = ParamMode::from_u32(code / 10_u32.pow(i as u32 + 2) % 10)?;
+            Some((mode, *data.get(1 + i)?))
+        }).collect::<Option<Vec<_>>>()?;
+
+        Some(Opcode {
+            root,
+            params,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<i64> {
+        let modes = self.params.iter().enumerate().map(|(i, (mode, _))| *mode as i64 * 10_i64.pow(i as u32 + 2)).sum::<i64>();
+
+        std::iter::once(self.root as i64 + modes).chain(self.params.iter().map(|(_, arg)| *arg)).collect()
+    }
+
+    pub fn size(&self) -> usize {
+        1 + self.params.len()
+    }
+
     fn read(arg_type: ParamMode, arg: i64, program: &[i64], relative_base: i64) -> i64 {
         match arg_type {
             ParamMode::Position => {
@@ -127,6 +189,17 @@ impl Opcode {
         }
     }
 
+    // The address this instruction will store to, if any
+    pub fn write_target(&self, relative_base: i64) -> Option<usize> {
+        let (mode, arg) = self.params[self.root.write_param()?];
+
+        match mode {
+            ParamMode::Position => Some(arg as usize),
+            ParamMode::Immediate => None,
+            ParamMode::Relative => Some((arg + relative_base) as usize),
+        }
+    }
+
     pub fn execute(&self, program: &mut [i64], input: &mut SliceIter<i64>, relative_base: &mut i64) -> (Option<usize>, Option<i64>) {
         match self.root {
             OpcodeRoot::Add => {
@@ -195,18 +268,31 @@ impl Opcode {
     }
 }
 
+#[derive(Clone)]
 struct ProgramState {
     pub program: Vec<i64>,
     pub input: Vec<i64>,
     pub input_idx: usize,
     pub pc: usize,
     pub relative_base: i64,
+    pub extensions: Extensions,
+    pub dialect: Dialect,
 }
 
 fn step_prog(state: &mut ProgramState) -> (Option<i64>, bool) {
-    let ProgramState { program, input, input_idx, pc, relative_base } = state;
+    let ProgramState { program, input, input_idx, pc, relative_base, extensions, dialect } = state;
+
+    if let Some(ext) = extensions.get((program[*pc] % 100) as u32) {
+        let (new_pc, out, consumed) = ext.execute(program, *pc, &input[*input_idx..], relative_base);
+        *pc = new_pc.unwrap_or(*pc + 1 + ext.arg_count);
+        *input_idx += consumed;
+        return (out, false);
+    }
 
     let opcode = Opcode::parse(&program[*pc..]);
+    if let Err(e) = dialect.check(&opcode, *pc) {
+        panic!("{}", e);
+    }
     let (new_pc, out) = opcode.execute(program, &mut input[*input_idx..].iter(), relative_base);
     if let Some(new_pc) = new_pc {
         *pc = new_pc;
@@ -221,14 +307,78 @@ fn step_prog(state: &mut ProgramState) -> (Option<i64>, bool) {
     (out, opcode.root == OpcodeRoot::Halt)
 }
 
-fn run_prog(mut program: Vec<i64>, input: &[i64]) -> Vec<i64> {
+// Whether the next step reads more input than is left, for builtin input
+// instructions and custom opcodes that declare they read input
+fn needs_input(state: &ProgramState) -> bool {
+    let ProgramState { program, input, input_idx, pc, extensions, .. } = state;
+    let code = program[*pc] % 100;
+    let wanted = match extensions.get(code as u32) {
+        Some(ext) => ext.inputs,
+        None if code == OpcodeRoot::Input as i64 => 1,
+        None => 0,
+    };
+    *input_idx + wanted > input.len()
+}
+
+// The memory a step is about to touch, worked out before it runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    pub pc: usize,
+    pub size: usize,
+    pub reads: Vec<usize>,
+    pub writes: Vec<usize>,
+}
+
+fn address(mode: ParamMode, arg: i64, relative_base: i64) -> Option<usize> {
+    match mode {
+        ParamMode::Position => Some(arg as usize),
+        ParamMode::Immediate => None,
+        ParamMode::Relative => Some((arg + relative_base) as usize),
+    }
+}
+
+fn step_access(state: &ProgramState) -> Access {
+    let ProgramState { program, pc, relative_base, extensions, .. } = state;
+
+    let (params, writes) = match extensions.get((program[*pc] % 100) as u32) {
+        Some(ext) => (ext.params(program, *pc), ext.writes.clone()),
+        None => {
+            let opcode = Opcode::parse(&program[*pc..]);
+            (opcode.params, opcode.root.write_param().into_iter().collect::<Vec<_>>())
+        }
+    };
+
+    let (mut reads, mut stores) = (Vec::new(), Vec::new());
+
+    for (idx, &(mode, arg)) in params.iter().enumerate() {
+        if let Some(addr) = address(mode, arg, *relative_base) {
+            if writes.contains(&idx) {
+                stores.push(addr);
+            } else {
+                reads.push(addr);
+            }
+        }
+    }
+
+    Access { pc: *pc, size: 1 + params.len(), reads, writes: stores }
+}
+
+fn run_prog(program: Vec<i64>, input: &[i64]) -> Vec<i64> {
+    run_prog_with(program, input, &Extensions::new())
+}
+
+fn pad_memory(program: &mut Vec<i64>) {
     if program.len() < 10000 {
             program.resize(10000, 0);
     }
+}
+
+fn run_prog_with(mut program: Vec<i64>, input: &[i64], extensions: &Extensions) -> Vec<i64> {
+    pad_memory(&mut program);
 
     let mut output = Vec::new();
 
-    let mut state = ProgramState { pc: 0, input: input.iter().copied().collect::<Vec<_>>(), input_idx: 0, program, relative_base: 0 };
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: extensions.clone(), dialect: Dialect::default() };
 
     loop {
         let (out, halted) = step_prog(&mut state);
@@ -243,76 +393,58 @@ fn run_prog(mut program: Vec<i64>, input: &[i64]) -> Vec<i64> {
     }
 }
 
-fn try_sequence_feedback(program: Vec<i64>, sequence: &[i64]) -> i64 {
-    let mut programs = sequence.iter().map(|n| {
-        ProgramState {
-            program: program.clone(),
-            input: vec![*n],
-            input_idx: 0,
-            pc: 0,
-            relative_base: 0,
-        }
-    }).collect::<Vec<_>>();
+#[derive(Debug, PartialEq)]
+pub struct RunResult {
+    pub output: Vec<i64>,
+    pub memory: Vec<i64>,
+    pub halted: bool,
+}
 
-    let mut halted = false;
+// Steps until the program halts or `max_steps` instructions have run, returning
+// whether it halted. Stops with a fault at an input it hasn't been given.
+fn run_state(state: &mut ProgramState, output: &mut Vec<i64>, max_steps: usize) -> Result<bool, Fault> {
+    for _ in 0..max_steps {
+        if needs_input(state) {
+            return Err(Fault::NeedsInput(state.pc));
+        }
 
-    let mut next_value = 0;
+        let (out, halted) = step_prog(state);
 
-    let run_until_one = |prog: &mut ProgramState| -> (Option<i64>, bool) {
-        loop {
-            match step_prog(prog) {
-                (None, false) => { /* Keep looping */ },
-                r => break r,
-            }
-        }
-    };
+        output.extend(out);
 
-    'overall: loop {
-        for i in 0..5 {
-            programs[i].input.push(next_value);
-            let (new_out, h) = run_until_one(&mut programs[i]);
-            if new_out == None { break 'overall; }
-            println!("{} Output is now {}", i, next_value);
-            next_value = new_out.unwrap();
+        if halted {
+            return Ok(true);
         }
     }
 
-    next_value
+    Ok(false)
 }
 
-fn try_sequence(program: Vec<i64>, sequence: &[i64]) -> i64 {
-    assert_eq!(sequence.len(), 5);
+// Day 2: the noun and verb that leave `target` at address 0
+fn find_end_value(program: Vec<i64>, target: i64) -> Option<(i64, i64)> {
+    let point = Sweep::new()
+        .patch(1, 0..100)
+        .patch(2, 0..100)
+        .threads(4)
+        .find_first(&program, |outcome| outcome.memory[0] == target)?;
 
-    let mut output = 0;
-
-    for s in sequence.iter() {
-        let new_output = run_prog(program.clone(), &[*s, output]);
-        println!("Output is now {}", output);
-        assert_eq!(new_output.len(), 1);
-        output = new_output[0];
-    }
-
-    println!("");
-
-    output
+    Some((point[0], point[1]))
 }
 
-fn try_sequence_state(program: Vec<i64>, sequence: &[i64]) -> i64 {
-    assert_eq!(sequence.len(), 5);
+fn try_sequence_feedback_in(pool: &mut Pool, sequence: &[i64]) -> i64 {
+    let mut programs = sequence.iter().map(|n| pool.checkout(&[], &[*n])).collect::<Vec<_>>();
 
-    let mut output = 0;
+    let last = futures::executor::block_on(machine::run_ring(&mut programs, 0));
 
-    for s in sequence.iter() {
-        let new_output = run_prog(program.clone(), &[*s, output]);
-        assert_eq!(new_output.len(), 1);
-        output = new_output[0];
+    for prog in programs {
+        pool.checkin(prog);
     }
 
-    output
+    last.expect("No output from the last amplifier")
 }
 
-
 fn find_highest(program: Vec<i64>) -> i64 {
+    let mut pool = Pool::new(program);
     let mut max_val = 0;
 
     for a in 0..5 {
@@ -320,7 +452,7 @@ fn find_highest(program: Vec<i64>) -> i64 {
             for c in (0..5).filter(|&n| n != a && n != b) {
                 for d in (0..5).filter(|&n| n != a && n != b && n != c) {
                     let e = (0..5).find(|&n| n != a && n != b && n != c && n != d).unwrap();
-                    let result = try_sequence_feedback(program.clone(), &[a+5, b+5, c+5, d+5, e+5]);
+                    let result = try_sequence_feedback_in(&mut pool, &[a+5, b+5, c+5, d+5, e+5]);
                     if max_val < result {
                         max_val = result;
                     }
@@ -332,8 +464,180 @@ fn find_highest(program: Vec<i64>) -> i64 {
     max_val
 }
 
+// Sweeps memory patches and inputs given as `mem:<addr>=<lo>..<hi>` and
+// `in:<index>=<lo>..<hi>`, looking for the first run that halts with exactly
+// `goal` as its output, or without a goal the run with the highest last output
+fn sweep_command(path: &str, specs: &[&str], goal: Option<Vec<i64>>) {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut sweep = Sweep::new().threads(threads).max_steps(selftest::MAX_STEPS);
+
+    for spec in specs.iter() {
+        sweep = match spec.strip_prefix("input=") {
+            Some(input) => sweep.base_input(&parse_values(input)),
+            None => sweep.dimension(spec).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }),
+        };
+    }
+
+    if sweep.is_empty() {
+        eprintln!("Nothing to sweep, one of the ranges is empty");
+        std::process::exit(1);
+    }
+
+    let program = parse_input(Path::new(path));
+
+    let found = match goal {
+        Some(goal) => sweep.find_first(&program, |outcome| outcome.halted && outcome.output == &goal[..]).map(|point| format!("{:?}", point)),
+        None => sweep.maximize(&program, |outcome| if outcome.halted { outcome.output.last().copied() } else { None })
+            .map(|(point, score)| format!("{:?}: {}", point, score)),
+    };
+
+    match found {
+        Some(found) => println!("Found {}", found),
+        None => {
+            println!("No run matched");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() {
-    assert_eq!(run_prog(parse_input(Path::new("./day5test.txt")), &[1]), vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 16348437]);
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    match args.iter().map(|a| a.as_str()).collect::<Vec<_>>()[..] {
+        ["disasm", path] => {
+            print!("{}", disasm::disassemble(&parse_input(Path::new(path))));
+            return;
+        }
+        ["decompile", path] => {
+            print!("{}", decompile::decompile(&parse_input(Path::new(path))));
+            return;
+        }
+        ["optimize", path] | ["optimize", path, "trust-relative"] => {
+            let options = optimize::OptimizeOptions { trust_relative: args.len() == 3 };
+            let optimized = optimize::optimize(&parse_input(Path::new(path)), &options);
+            println!("{}", optimized.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(","));
+            return;
+        }
+        ["coverage", path, input] | ["coverage", path, input, _] => {
+            let input = parse_values(input);
+            let program = parse_input(Path::new(path));
+            let len = program.len();
+
+            let (output, coverage) = coverage::run_with_coverage(program, &input);
+            println!("Output: {:?}", output);
+            print!("{}", coverage.report(len));
+
+            if let Some(svg) = args.get(3) {
+                std::fs::write(svg, coverage.svg(len, 64)).unwrap();
+            }
+            return;
+        }
+        ["trace", path, input, sink] => {
+            let sink = events::open_sink(sink).unwrap_or_else(|e| panic!("Could not open {}: {}", sink, e));
+            let output = events::run_traced(parse_input(Path::new(path)), &parse_values(input), sink).unwrap();
+            println!("Output: {:?}", output);
+            return;
+        }
+        ["serve", path, target] => {
+            remote::serve(parse_input(Path::new(path)), target).unwrap_or_else(|e| panic!("Could not serve on {}: {}", target, e));
+            return;
+        }
+        ["run", path, input, ref options @ ..] if options.len() <= 2 => {
+            let mut dialect = Dialect::default();
+            let mut extensions = Extensions::new();
+
+            for option in options.iter() {
+                match (*option, option.parse::<Dialect>()) {
+                    ("ext", _) => extensions = extension::stdlib(),
+                    (_, Ok(d)) => dialect = d,
+                    (_, Err(e)) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            match dialect::run_prog_in(parse_input(Path::new(path)), &parse_values(input), dialect, &extensions) {
+                Ok(output) => println!("Output: {:?}", output),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        ["nounverb", path, target] => {
+            let target = target.parse::<i64>().unwrap_or_else(|_| panic!("Invalid target {}", target));
+            match find_end_value(parse_input(Path::new(path)), target) {
+                Some((noun, verb)) => println!("Noun {}, verb {}: {}", noun, verb, 100 * noun + verb),
+                None => {
+                    println!("No noun and verb give {}", target);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        ["sweep", path, ref specs @ .., "max"] => {
+            sweep_command(path, specs, None);
+            return;
+        }
+        ["sweep", path, ref specs @ .., "find", outputs] => {
+            sweep_command(path, specs, Some(parse_values(outputs)));
+            return;
+        }
+        ["batch", path, inputs] => {
+            let configs = inputs.split(';').map(|i| pool::Config { overrides: Vec::new(), input: parse_values(i) }).collect::<Vec<_>>();
+            let mut pool = Pool::new(parse_input(Path::new(path)));
+            pool.max_steps = selftest::MAX_STEPS;
+
+            for (config, result) in configs.iter().zip(pool.batch(&configs, |_, output, halted| (output.to_vec(), halted))) {
+                match result {
+                    Ok((output, halted)) => {
+                        let suffix = if halted { "" } else { " (did not halt)" };
+                        println!("{:?}: {:?}{}", config.input, output, suffix);
+                    }
+                    Err(fault) => println!("{:?}: {}", config.input, fault),
+                }
+            }
+            return;
+        }
+        ["amplify", path] => {
+            println!("Highest signal: {}", find_highest(parse_input(Path::new(path))));
+            return;
+        }
+        ["encode", path, out] => {
+            let file = loader::load(Path::new(path)).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            std::fs::write(out, loader::encode_binary(&file)).unwrap();
+            return;
+        }
+        ["test"] | ["test", _] => {
+            let dir = args.get(1).map_or(".", |d| d.as_str());
+            let report = selftest::test_dir(Path::new(dir), selftest::MAX_STEPS).unwrap_or_else(|e| panic!("Could not read {}: {}", dir, e));
+            print!("{}", report.summary());
+            if report.failed() != 0 {
+                std::process::exit(1);
+            }
+            return;
+        }
+        ["watch", path, input] => {
+            match watchdog::run_watched(parse_input(Path::new(path)), &parse_values(input)) {
+                Ok(output) => println!("Output: {:?}", output),
+                Err(stuck) => {
+                    println!("Infinite loop over {}-{} every {} steps (caught at step {})", stuck.start, stuck.end - 1, stuck.period, stuck.step);
+                    std::process::exit(2);
+                }
+            }
+            return;
+        }
+        [] => {}
+        _ => {
+            eprintln!("Usage: day9 [run <program> <inputs> [day2|day5|day9] [ext] | nounverb <program> <target> | amplify <program> | sweep <program> <dims> [input=<inputs>] find <outputs>|max | batch <program> <inputs>[;<inputs>...] | disasm|decompile <program> | optimize <program> [trust-relative] | coverage <program> <inputs> [svg] | trace <program> <inputs> <file|tcp:addr|unix:path> | serve <program> <tcp:addr|unix:path> | watch <program> <inputs> | encode <program> <binary> | test [dir]]");
+            std::process::exit(1);
+        }
+    }
 
     let prog = parse_input(Path::new("./quine.txt"));
 
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+
+use crate::{Opcode, OpcodeRoot, ParamMode};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OptimizeOptions {
+    // Assume relative-mode accesses never land on the program's own code,
+    // which holds for programs that keep their stack past the end of the image
+    pub trust_relative: bool,
+}
+
+struct Analysis {
+    // Every instruction reachable from address 0, keyed by address
+    code: BTreeMap<usize, Opcode>,
+    // Every address read or written through a position-mode parameter
+    touched: HashSet<usize>,
+    uses_relative: bool,
+}
+
+enum Rewrite {
+    Keep,
+    Replace(Opcode),
+    Remove,
+}
+
+fn is_jump(root: OpcodeRoot) -> bool {
+    root == OpcodeRoot::JumpTrue || root == OpcodeRoot::JumpFalse
+}
+
+// Walks every path from address 0. Gives up (None) on anything that would
+// make the set of code or accessed addresses unknowable: undecodable words,
+// jumps through memory, overlapping instructions, or untrusted relative accesses.
+fn analyze(program: &[i64], options: &OptimizeOptions) -> Option<Analysis> {
+    let mut code = BTreeMap::new();
+    let mut touched = HashSet::new();
+    let mut uses_relative = false;
+
+    let mut pending = vec![0];
+
+    while let Some(pc) = pending.pop() {
+        if code.contains_key(&pc) {
+            continue;
+        }
+
+        let opcode = Opcode::try_parse(program.get(pc..)?)?;
+
+        for (idx, &(mode, arg)) in opcode.params.iter().enumerate() {
+            match mode {
+                ParamMode::Position if is_jump(opcode.root) && idx == 1 => return None,
+                ParamMode::Position => { touched.insert(usize::try_from(arg).ok()?); }
+                ParamMode::Immediate => {}
+                ParamMode::Relative if options.trust_relative => uses_relative = true,
+                ParamMode::Relative => return None,
+            }
+        }
+
+        let next = pc + opcode.size();
+
+        match opcode.root {
+            OpcodeRoot::Halt => {}
+            OpcodeRoot::JumpTrue | OpcodeRoot::JumpFalse => {
+                let (target_mode, target) = opcode.params[1];
+                if target_mode != ParamMode::Immediate {
+                    return None;
+                }
+                pending.push(usize::try_from(target).ok()?);
+
+                let always = match opcode.params[0] {
+                    (ParamMode::Immediate, cond) => (cond != 0) == (opcode.root == OpcodeRoot::JumpTrue),
+                    _ => false,
+                };
+                if !always {
+                    pending.push(next);
+                }
+            }
+            _ => pending.push(next),
+        }
+
+        code.insert(pc, opcode);
+    }
+
+    let mut end = 0;
+    for (&pc, opcode) in code.iter() {
+        if pc < end {
+            return None;
+        }
+        end = pc + opcode.size();
+    }
+
+    Some(Analysis { code, touched, uses_relative })
+}
+
+fn fold(root: OpcodeRoot, lhs: i64, rhs: i64) -> Option<i64> {
+    match root {
+        OpcodeRoot::Add => lhs.checked_add(rhs),
+        OpcodeRoot::Multiply => lhs.checked_mul(rhs),
+        OpcodeRoot::LessThan => Some((lhs < rhs) as i64),
+        OpcodeRoot::Equals => Some((lhs == rhs) as i64),
+        _ => None,
+    }
+}
+
+fn constant(value: i64, dest: (ParamMode, i64)) -> Opcode {
+    Opcode { root: OpcodeRoot::Add, params: vec![(ParamMode::Immediate, value), (ParamMode::Immediate, 0), dest] }
+}
+
+fn simplify(pc: usize, opcode: &Opcode) -> Rewrite {
+    let p = &opcode.params;
+
+    match opcode.root {
+        OpcodeRoot::Add | OpcodeRoot::Multiply | OpcodeRoot::LessThan | OpcodeRoot::Equals => {
+            if let ((ParamMode::Immediate, lhs), (ParamMode::Immediate, rhs)) = (p[0], p[1]) {
+                return match fold(opcode.root, lhs, rhs) {
+                    Some(value) if opcode.params[..2] != constant(value, p[2]).params[..2] => Rewrite::Replace(constant(value, p[2])),
+                    _ => Rewrite::Keep,
+                };
+            }
+
+            let other = match (p[0], p[1]) {
+                (x, (ParamMode::Immediate, n)) | ((ParamMode::Immediate, n), x) => Some((x, n)),
+                _ => None,
+            };
+
+            match (opcode.root, other) {
+                (OpcodeRoot::Multiply, Some((_, 0))) => Rewrite::Replace(constant(0, p[2])),
+                (OpcodeRoot::Multiply, Some((x, 1))) => {
+                    Rewrite::Replace(Opcode { root: OpcodeRoot::Add, params: vec![x, (ParamMode::Immediate, 0), p[2]] })
+                }
+                // Storing a cell back onto itself
+                (OpcodeRoot::Add, Some(((ParamMode::Position, src), 0))) if p[2] == (ParamMode::Position, src) => Rewrite::Remove,
+                _ => Rewrite::Keep,
+            }
+        }
+        OpcodeRoot::JumpTrue | OpcodeRoot::JumpFalse => {
+            if p[1] == (ParamMode::Immediate, (pc + opcode.size()) as i64) {
+                return Rewrite::Remove;
+            }
+
+            match p[0] {
+                (ParamMode::Immediate, cond) if (cond != 0) == (opcode.root == OpcodeRoot::JumpTrue) => {
+                    let jump = Opcode { root: OpcodeRoot::JumpTrue, params: vec![(ParamMode::Immediate, 1), p[1]] };
+                    if jump.params == opcode.params && jump.root == opcode.root {
+                        Rewrite::Keep
+                    } else {
+                        Rewrite::Replace(jump)
+                    }
+                }
+                (ParamMode::Immediate, _) => Rewrite::Remove,
+                _ => Rewrite::Keep,
+            }
+        }
+        _ => Rewrite::Keep,
+    }
+}
+
+// Peephole-simplifies every instruction that nothing reads or writes as data,
+// then, if the program only addresses memory absolutely, squeezes out the
+// instructions that became no-ops and relocates every address to match.
+pub fn optimize(program: &[i64], options: &OptimizeOptions) -> Vec<i64> {
+    let analysis = match analyze(program, options) {
+        Some(a) => a,
+        None => return program.to_vec(),
+    };
+
+    let stable = |pc: usize, opcode: &Opcode| (pc..pc + opcode.size()).all(|a| !analysis.touched.contains(&a));
+
+    let mut code = BTreeMap::new();
+    let mut removed = Vec::new();
+
+    for (&pc, opcode) in analysis.code.iter() {
+        let mut current = opcode.clone();
+
+        if stable(pc, opcode) {
+            loop {
+                match simplify(pc, &current) {
+                    Rewrite::Keep => break,
+                    Rewrite::Replace(new) => current = new,
+                    Rewrite::Remove => {
+                        removed.push((pc, opcode.size()));
+                        break;
+                    }
+                }
+            }
+        }
+
+        code.insert(pc, current);
+    }
+
+    let mut result = program.to_vec();
+
+    if analysis.uses_relative {
+        // Relative addresses can't be relocated, so no-ops stay as never-taken jumps
+        for (&pc, opcode) in code.iter() {
+            let never = Opcode { root: OpcodeRoot::JumpFalse, params: vec![(ParamMode::Immediate, 1), (ParamMode::Immediate, 0)] };
+            let words = if removed.iter().any(|&(r, _)| r == pc) && opcode.size() == never.size() {
+                never.encode()
+            } else {
+                opcode.encode()
+            };
+            result[pc..pc + words.len()].copy_from_slice(&words);
+        }
+        return result;
+    }
+
+    let relocate = |addr: i64| -> i64 {
+        let shift = removed.iter().filter(|&&(pc, _)| (pc as i64) < addr).map(|&(_, size)| size as i64).sum::<i64>();
+        addr - shift
+    };
+
+    for (&pc, opcode) in code.iter() {
+        let mut opcode = opcode.clone();
+        let root = opcode.root;
+        for (idx, param) in opcode.params.iter_mut().enumerate() {
+            let is_target = is_jump(root) && idx == 1;
+            if param.0 == ParamMode::Position || is_target {
+                param.1 = relocate(param.1);
+            }
+        }
+        result[pc..pc + opcode.size()].copy_from_slice(&opcode.encode());
+    }
+
+    for &(pc, size) in removed.iter().rev() {
+        result.drain(pc..pc + size);
+    }
+
+    result
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{pad_memory, run_state, ProgramState};
+use crate::dialect::Dialect;
+use crate::extension::Extensions;
+
+// One run: memory patches applied over the pristine image, plus the input queue
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub overrides: Vec<(usize, i64)>,
+    pub input: Vec<i64>,
+}
+
+// Why a run stopped before halting or running out of steps
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    // The input instruction at this pc had nothing left to read
+    NeedsInput(usize),
+    // The machine panicked, like on a bad opcode or address
+    Panicked(String),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::NeedsInput(pc) => write!(f, "faulted: out of input at pc {}", pc),
+            Fault::Panicked(message) => write!(f, "faulted: {}", message),
+        }
+    }
+}
+
+// Keeps finished machines around so later runs can reset them to the
+// pristine image in place instead of cloning a fresh copy every time.
+pub struct Pool {
+    image: Vec<i64>,
+    idle: Vec<ProgramState>,
+    output: Vec<i64>,
+    pub extensions: Extensions,
+    pub dialect: Dialect,
+    pub max_steps: usize,
+}
+
+impl Pool {
+    pub fn new(mut image: Vec<i64>) -> Pool {
+        pad_memory(&mut image);
+
+        Pool {
+            image,
+            idle: Vec::new(),
+            output: Vec::new(),
+            extensions: Extensions::new(),
+            dialect: Dialect::default(),
+            max_steps: usize::MAX,
+        }
+    }
+
+    pub fn checkout(&mut self, overrides: &[(usize, i64)], input: &[i64]) -> ProgramState {
+        let mut state = match self.idle.pop() {
+            Some(mut state) => {
+                state.program.copy_from_slice(&self.image);
+                state.input.clear();
+                state.input.extend_from_slice(input);
+                state.input_idx = 0;
+                state.pc = 0;
+                state.relative_base = 0;
+                state.extensions = self.extensions.clone();
+                state.dialect = self.dialect;
+                state
+            }
+            None => ProgramState {
+                program: self.image.clone(),
+                input: input.to_vec(),
+                input_idx: 0,
+                pc: 0,
+                relative_base: 0,
+                extensions: self.extensions.clone(),
+                dialect: self.dialect,
+            },
+        };
+
+        for &(addr, value) in overrides.iter() {
+            state.program[addr] = value;
+        }
+
+        state
+    }
+
+    pub fn checkin(&mut self, state: ProgramState) {
+        if state.program.len() == self.image.len() {
+            self.idle.push(state);
+        }
+    }
+
+    // Runs one configuration to completion (or the step limit) and hands
+    // the final machine and its output to `extract`, which picks out the result.
+    // A run that faults gives the fault instead, without affecting later runs.
+    pub fn evaluate<T, F>(&mut self, config: &Config, mut extract: F) -> Result<T, Fault>
+        where F: FnMut(&ProgramState, &[i64], bool) -> T
+    {
+        let mut state = self.checkout(&config.overrides, &config.input);
+
+        let mut output = std::mem::take(&mut self.output);
+        output.clear();
+
+        let run = panic::catch_unwind(AssertUnwindSafe(|| run_state(&mut state, &mut output, self.max_steps)));
+        let result = match run {
+            Ok(Ok(halted)) => Ok(extract(&state, &output, halted)),
+            Ok(Err(fault)) => Err(fault),
+            Err(e) => {
+                let message = e.downcast_ref::<String>().cloned()
+                    .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                Err(Fault::Panicked(message))
+            }
+        };
+
+        self.output = output;
+        self.checkin(state);
+
+        result
+    }
+
+    pub fn batch<T, F>(&mut self, configs: &[Config], mut extract: F) -> Vec<Result<T, Fault>>
+        where F: FnMut(&ProgramState, &[i64], bool) -> T
+    {
+        configs.iter().map(|config| self.evaluate(config, &mut extract)).collect()
+    }
+}
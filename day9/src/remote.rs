@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{needs_input, pad_memory, step_prog, ProgramState};
+use crate::dialect::Dialect;
+use crate::extension::Extensions;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Running,
+    // Stopped at an input instruction with nothing queued
+    Waiting,
+    Halted,
+    Faulted(String),
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Running => "running",
+            Status::Waiting => "waiting",
+            Status::Halted => "halted",
+            Status::Faulted(_) => "faulted",
+        }
+    }
+}
+
+// One machine driven by text commands, one command per line and one reply per command:
+//
+//   input 1,2,3   queue input values
+//   step [n]      run at most n instructions (default 1)
+//   run           run until halted or waiting for input
+//   next          run until one output is produced, and pull it
+//   output        pull every pending output
+//   state         pc, relative base, status and queue sizes
+//   peek a        read address a
+//   poke a v      write v to address a
+//   reset         reload the original program
+//   quit          close the connection
+//
+// Replies start with `ok` or `err`. `run` and `next` give up after
+// `max_steps` instructions with `ok running <n>`, so a looping program
+// can't stop the session answering.
+pub struct Session {
+    image: Vec<i64>,
+    state: ProgramState,
+    outputs: VecDeque<i64>,
+    status: Status,
+    pub max_steps: usize,
+}
+
+pub const MAX_STEPS: usize = 10_000_000;
+
+impl Session {
+    pub fn new(mut image: Vec<i64>) -> Session {
+        pad_memory(&mut image);
+
+        let state = ProgramState { pc: 0, input: Vec::new(), input_idx: 0, program: image.clone(), relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+
+        Session { image, state, outputs: VecDeque::new(), status: Status::Running, max_steps: MAX_STEPS }
+    }
+
+    // Runs one instruction unless the machine can't make progress
+    fn step(&mut self) -> bool {
+        if let Status::Halted | Status::Faulted(_) = self.status {
+            return false;
+        }
+
+        if needs_input(&self.state) {
+            self.status = Status::Waiting;
+            return false;
+        }
+
+        let state = &mut self.state;
+        match panic::catch_unwind(AssertUnwindSafe(|| step_prog(state))) {
+            Ok((out, halted)) => {
+                self.outputs.extend(out);
+                self.status = if halted { Status::Halted } else { Status::Running };
+                !halted
+            }
+            Err(e) => {
+                let message = e.downcast_ref::<String>().cloned()
+                    .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                self.status = Status::Faulted(message);
+                false
+            }
+        }
+    }
+
+    fn stopped(&self) -> String {
+        match &self.status {
+            Status::Faulted(message) => format!("err faulted: {}", message),
+            status => format!("ok {}", status.name()),
+        }
+    }
+
+    // Like `stopped`, but says how far a run got if it ran out of steps
+    fn stopped_after(&self, ran: usize) -> String {
+        match self.status {
+            Status::Running => format!("{} {}", self.stopped(), ran),
+            _ => self.stopped(),
+        }
+    }
+
+    fn join(values: impl Iterator<Item = i64>) -> String {
+        values.map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+    }
+
+    fn address(&self, text: &str) -> Result<usize, String> {
+        text.parse::<usize>().ok().filter(|&a| a < self.state.program.len()).ok_or_else(|| format!("bad address {}", text))
+    }
+
+    // Returns the reply, or None if the client asked to quit
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        let reply = match words[..] {
+            ["input", values] => match values.split(',').map(|v| v.trim().parse::<i64>()).collect::<Result<Vec<_>, _>>() {
+                Ok(values) => {
+                    self.state.input.extend(values);
+                    if self.status == Status::Waiting {
+                        self.status = Status::Running;
+                    }
+                    "ok".to_string()
+                }
+                Err(_) => format!("err bad values {}", values),
+            },
+            ["step"] | ["step", _] => {
+                let count = match words.get(1).map(|n| n.parse::<usize>()) {
+                    None => Ok(1),
+                    Some(Ok(n)) => Ok(n),
+                    Some(Err(_)) => Err(format!("err bad count {}", words[1])),
+                };
+                match count {
+                    Ok(count) => {
+                        let ran = (0..count).take_while(|_| self.step()).count();
+                        format!("{} {}", self.stopped(), ran)
+                    }
+                    Err(e) => e,
+                }
+            }
+            ["run"] => {
+                let ran = (0..self.max_steps).take_while(|_| self.step()).count();
+                self.stopped_after(ran)
+            }
+            ["next"] => {
+                let ran = (0..self.max_steps).take_while(|_| self.outputs.is_empty() && self.step()).count();
+                match self.outputs.pop_front() {
+                    Some(value) => format!("ok {}", value),
+                    None => self.stopped_after(ran),
+                }
+            }
+            ["output"] => format!("ok {}", Session::join(self.outputs.drain(..))).trim_end().to_string(),
+            ["state"] => format!(
+                "ok pc={} rb={} status={} inputs={} outputs={}",
+                self.state.pc, self.state.relative_base, self.status.name(),
+                self.state.input.len() - self.state.input_idx, self.outputs.len(),
+            ),
+            ["peek", addr] => match self.address(addr) {
+                Ok(addr) => format!("ok {}", self.state.program[addr]),
+                Err(e) => format!("err {}", e),
+            },
+            ["poke", addr, value] => match (self.address(addr), value.parse::<i64>()) {
+                (Ok(addr), Ok(value)) => {
+                    self.state.program[addr] = value;
+                    "ok".to_string()
+                }
+                (Err(e), _) => format!("err {}", e),
+                (_, Err(_)) => format!("err bad value {}", value),
+            },
+            ["reset"] => {
+                let max_steps = self.max_steps;
+                *self = Session::new(self.image.clone());
+                self.max_steps = max_steps;
+                "ok".to_string()
+            }
+            ["quit"] => return None,
+            _ => format!("err unknown command {:?}", line.trim()),
+        };
+
+        Some(reply)
+    }
+}
+
+pub fn serve_connection<R: io::Read, W: Write>(reader: R, mut writer: W, image: Vec<i64>) -> io::Result<()> {
+    let mut session = Session::new(image);
+
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match session.handle(&line) {
+            Some(reply) => {
+                writeln!(writer, "{}", reply)?;
+                writer.flush()?;
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+// Listens on `tcp:host:port` or `unix:/path`, giving every connection its own machine
+pub fn serve(image: Vec<i64>, target: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        if let Some(path) = target.strip_prefix("unix:") {
+            let listener = std::os::unix::net::UnixListener::bind(path)?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let image = image.clone();
+                std::thread::spawn(move || serve_connection(stream.try_clone()?, stream, image));
+            }
+            return Ok(());
+        }
+    }
+
+    let addr = target.strip_prefix("tcp:").unwrap_or(target);
+    let listener = TcpListener::bind(addr)?;
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let image = image.clone();
+        std::thread::spawn(move || serve_connection(stream.try_clone()?, stream, image));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::fmt::Write;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use crate::loader::{self, Check, ProgramFile};
+use crate::cached::run_prog_limited;
+
+// Enough for every puzzle input so far, while still catching runaway programs
+pub const MAX_STEPS: usize = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Fail(Vec<i64>),
+    // The program faulted or never halted
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub check: Check,
+    pub verdict: Verdict,
+}
+
+pub fn run_check(code: &[i64], check: &Check, max_steps: usize) -> Verdict {
+    let run = panic::catch_unwind(AssertUnwindSafe(|| run_prog_limited(code.to_vec(), &check.input, max_steps)));
+
+    match run {
+        Ok(result) if !result.halted => Verdict::Error(format!("did not halt within {} steps", max_steps)),
+        Ok(result) if result.output == check.output => Verdict::Pass,
+        Ok(result) => Verdict::Fail(result.output),
+        Err(e) => {
+            let message = e.downcast_ref::<String>().cloned()
+                .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_default();
+            Verdict::Error(format!("faulted: {}", message))
+        }
+    }
+}
+
+pub fn run_checks(file: &ProgramFile, max_steps: usize) -> Vec<CheckResult> {
+    file.checks.iter().map(|check| CheckResult { check: check.clone(), verdict: run_check(&file.code, check, max_steps) }).collect()
+}
+
+// Every annotated program in one directory
+#[derive(Debug, Default)]
+pub struct Report {
+    // File name, and either its results or why it couldn't be loaded
+    pub programs: Vec<(String, Result<Vec<CheckResult>, String>)>,
+}
+
+fn join(values: &[i64]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.programs.iter().filter_map(|(_, r)| r.as_ref().ok()).flatten().filter(|r| r.verdict == Verdict::Pass).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.programs.iter().map(|(_, r)| match r {
+            Ok(results) => results.iter().filter(|r| r.verdict != Verdict::Pass).count(),
+            Err(_) => 1,
+        }).sum()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut result = String::new();
+
+        for (name, results) in self.programs.iter() {
+            match results {
+                Err(e) => writeln!(result, "FAIL {}: {}", name, e).unwrap(),
+                Ok(results) => for (idx, r) in results.iter().enumerate() {
+                    let input = if r.check.input.is_empty() { "no input".to_string() } else { format!("input {}", join(&r.check.input)) };
+                    let label = format!("{} #{} ({})", name, idx + 1, input);
+                    match &r.verdict {
+                        Verdict::Pass => writeln!(result, "pass {}", label).unwrap(),
+                        Verdict::Fail(output) => writeln!(
+                            result, "FAIL {}: expected {}, got {}", label, join(&r.check.output), join(output),
+                        ).unwrap(),
+                        Verdict::Error(e) => writeln!(result, "FAIL {}: {}", label, e).unwrap(),
+                    }
+                },
+            }
+        }
+
+        writeln!(result, "{} passed, {} failed", self.passed(), self.failed()).unwrap();
+
+        result
+    }
+}
+
+// Runs the checks in every .txt or .icb file under `dir` that has any
+pub fn test_dir(dir: &Path, max_steps: usize) -> io::Result<Report> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.is_file() && matches!(p.extension().and_then(|e| e.to_str()), Some("txt") | Some("icb")));
+    paths.sort();
+
+    let mut report = Report::default();
+
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+
+        match loader::load(&path) {
+            Ok(file) if file.checks.is_empty() => {}
+            Ok(file) => report.programs.push((name, Ok(run_checks(&file, max_steps)))),
+            Err(e) => report.programs.push((name, Err(e.to_string()))),
+        }
+    }
+
+    Ok(report)
+}
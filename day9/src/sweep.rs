@@ -0,0 +1,228 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::pool::{Config, Pool};
+
+#[derive(Debug, Clone)]
+enum Dimension {
+    Memory(usize, Range<i64>),
+    Input(usize, Range<i64>),
+}
+
+impl Dimension {
+    fn values(&self) -> &Range<i64> {
+        match self {
+            Dimension::Memory(_, values) | Dimension::Input(_, values) => values,
+        }
+    }
+}
+
+// What a predicate or objective gets to look at once a run finishes
+pub struct Outcome<'a> {
+    pub memory: &'a [i64],
+    pub output: &'a [i64],
+    pub halted: bool,
+}
+
+// Every combination of the given memory patches and input values, searched in
+// order with the first dimension varying slowest
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    dims: Vec<Dimension>,
+    input: Vec<i64>,
+    threads: usize,
+    max_steps: usize,
+}
+
+impl Default for Sweep {
+    fn default() -> Sweep {
+        Sweep::new()
+    }
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            dims: Vec::new(),
+            input: Vec::new(),
+            threads: 1,
+            max_steps: usize::MAX,
+        }
+    }
+
+    pub fn patch(mut self, addr: usize, values: Range<i64>) -> Sweep {
+        self.dims.push(Dimension::Memory(addr, values));
+        self
+    }
+
+    // Input values not covered by a dimension come from `base_input`, or 0
+    pub fn input(mut self, index: usize, values: Range<i64>) -> Sweep {
+        self.dims.push(Dimension::Input(index, values));
+        self
+    }
+
+    // Adds a dimension written as `mem:<addr>=<lo>..<hi>` or `in:<index>=<lo>..<hi>`
+    pub fn dimension(self, spec: &str) -> Result<Sweep, String> {
+        let bad = || format!("Bad dimension {:?}, expected mem:<addr>=<lo>..<hi> or in:<index>=<lo>..<hi>", spec);
+
+        let (kind, rest) = spec.split_once(':').ok_or_else(bad)?;
+        let (at, range) = rest.split_once('=').ok_or_else(bad)?;
+        let (lo, hi) = range.split_once("..").ok_or_else(bad)?;
+
+        let at = at.parse::<usize>().map_err(|_| bad())?;
+        let values = lo.parse::<i64>().map_err(|_| bad())?..hi.parse::<i64>().map_err(|_| bad())?;
+
+        match kind {
+            "mem" => Ok(self.patch(at, values)),
+            "in" => Ok(self.input(at, values)),
+            _ => Err(bad()),
+        }
+    }
+
+    pub fn base_input(mut self, input: &[i64]) -> Sweep {
+        self.input = input.to_vec();
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Sweep {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: usize) -> Sweep {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.dims.iter().map(|d| (d.values().end - d.values().start).max(0) as usize).product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The value each dimension takes at position `idx` of the sweep
+    fn point(&self, mut idx: usize) -> Vec<i64> {
+        let mut point = vec![0; self.dims.len()];
+
+        for (value, dim) in point.iter_mut().zip(self.dims.iter()).rev() {
+            let size = (dim.values().end - dim.values().start) as usize;
+            *value = dim.values().start + (idx % size) as i64;
+            idx /= size;
+        }
+
+        point
+    }
+
+    fn config(&self, point: &[i64]) -> Config {
+        let mut config = Config { overrides: Vec::new(), input: self.input.clone() };
+
+        for (dim, &value) in self.dims.iter().zip(point) {
+            match dim {
+                Dimension::Memory(addr, _) => config.overrides.push((*addr, value)),
+                Dimension::Input(index, _) => {
+                    if config.input.len() <= *index {
+                        config.input.resize(index + 1, 0);
+                    }
+                    config.input[*index] = value;
+                }
+            }
+        }
+
+        config
+    }
+
+    // Runs `visit` on every point in parallel, with thread `t` taking points
+    // t, t + threads, ... in order. `visit` returns false to stop that thread.
+    // Points whose run faults are skipped without a visit.
+    fn run<F>(&self, image: &[i64], visit: F)
+        where F: Fn(usize, &Outcome) -> bool + Sync
+    {
+        let len = self.len();
+
+        // Patches past the end of the padded image grow it, like a write there would
+        let mut image = image.to_vec();
+        for dim in self.dims.iter() {
+            if let Dimension::Memory(addr, _) = dim {
+                if image.len() <= *addr {
+                    image.resize(addr + 1, 0);
+                }
+            }
+        }
+        let image = &image;
+
+        std::thread::scope(|scope| {
+            for t in 0..self.threads {
+                let visit = &visit;
+
+                scope.spawn(move || {
+                    let mut pool = Pool::new(image.to_vec());
+                    pool.max_steps = self.max_steps;
+
+                    for idx in (t..len).step_by(self.threads) {
+                        let point = self.point(idx);
+                        // A run that faults is never a match, so the search carries on past it
+                        let keep_going = pool.evaluate(&self.config(&point), |state, output, halted| {
+                            visit(idx, &Outcome { memory: &state.program, output, halted })
+                        }).unwrap_or(true);
+
+                        if !keep_going {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    // The earliest point in sweep order whose run satisfies `predicate`.
+    // Threads stop as soon as they pass a point that has already matched.
+    // Runs that fault never match.
+    pub fn find_first<P>(&self, image: &[i64], predicate: P) -> Option<Vec<i64>>
+        where P: Fn(&Outcome) -> bool + Sync
+    {
+        let best = AtomicUsize::new(usize::MAX);
+
+        self.run(image, |idx, outcome| {
+            if idx > best.load(Ordering::Relaxed) {
+                return false;
+            }
+            if predicate(outcome) {
+                best.fetch_min(idx, Ordering::Relaxed);
+                return false;
+            }
+            true
+        });
+
+        match best.into_inner() {
+            usize::MAX => None,
+            idx => Some(self.point(idx)),
+        }
+    }
+
+    // The point with the highest score, preferring the earliest on ties.
+    // Runs that fault, or that the objective rejects with None, are skipped.
+    pub fn maximize<O>(&self, image: &[i64], objective: O) -> Option<(Vec<i64>, i64)>
+        where O: Fn(&Outcome) -> Option<i64> + Sync
+    {
+        let best: Mutex<Option<(i64, usize)>> = Mutex::new(None);
+
+        self.run(image, |idx, outcome| {
+            if let Some(score) = objective(outcome) {
+                let mut best = best.lock().unwrap();
+                let better = match *best {
+                    None => true,
+                    Some((s, i)) => score > s || (score == s && idx < i),
+                };
+                if better {
+                    *best = Some((score, idx));
+                }
+            }
+            true
+        });
+
+        best.into_inner().unwrap().map(|(score, idx)| (self.point(idx), score))
+    }
+}
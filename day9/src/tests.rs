@@ -0,0 +1,878 @@
+use super::*;
+use crate::pool::Config;
+
+// Runs a program to completion without padding its memory,
+// returning the outputs and the final memory image
+fn run_full(program: Vec<i64>, input: &[i64]) -> (Vec<i64>, Vec<i64>) {
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+    let mut output = Vec::new();
+
+    loop {
+        let (out, halted) = step_prog(&mut state);
+        output.extend(out);
+        if halted {
+            return (output, state.program);
+        }
+    }
+}
+
+fn memory_after(program: &[i64]) -> Vec<i64> {
+    run_full(program.to_vec(), &[]).1
+}
+
+#[test]
+fn add() {
+    assert_eq!(memory_after(&[1, 0, 0, 0, 99]), vec![2, 0, 0, 0, 99]);
+    assert_eq!(memory_after(&[1101, 7, -9, 5, 99, 0]), vec![1101, 7, -9, 5, 99, -2]);
+}
+
+#[test]
+fn multiply() {
+    assert_eq!(memory_after(&[2, 3, 0, 3, 99]), vec![2, 3, 0, 6, 99]);
+    assert_eq!(memory_after(&[2, 4, 4, 5, 99, 0]), vec![2, 4, 4, 5, 99, 9801]);
+}
+
+#[test]
+fn input_output() {
+    assert_eq!(run_prog(vec![3, 0, 4, 0, 99], &[-17]), vec![-17]);
+    assert_eq!(run_prog(vec![3, 9, 3, 10, 4, 10, 4, 9, 99], &[1, 2]), vec![2, 1]);
+}
+
+#[test]
+fn jumps() {
+    // Jump-if-true skips the first output only when the input is nonzero
+    let prog = vec![3, 11, 1005, 11, 7, 104, 1, 104, 2, 99, 0, 0];
+    assert_eq!(run_prog(prog.clone(), &[0]), vec![1, 2]);
+    assert_eq!(run_prog(prog, &[5]), vec![2]);
+
+    let prog = vec![3, 11, 1006, 11, 7, 104, 1, 104, 2, 99, 0, 0];
+    assert_eq!(run_prog(prog.clone(), &[0]), vec![2]);
+    assert_eq!(run_prog(prog, &[5]), vec![1, 2]);
+}
+
+#[test]
+fn comparisons() {
+    assert_eq!(run_prog(vec![1107, 3, 4, 7, 4, 7, 99, 0], &[]), vec![1]);
+    assert_eq!(run_prog(vec![1107, 4, 4, 7, 4, 7, 99, 0], &[]), vec![0]);
+    assert_eq!(run_prog(vec![1108, 4, 4, 7, 4, 7, 99, 0], &[]), vec![1]);
+    assert_eq!(run_prog(vec![1108, -4, 4, 7, 4, 7, 99, 0], &[]), vec![0]);
+}
+
+#[test]
+fn halt_stops_immediately() {
+    assert_eq!(run_full(vec![99, 104, 1], &[]), (vec![], vec![99, 104, 1]));
+}
+
+#[test]
+fn parameter_modes() {
+    assert_eq!(memory_after(&[1002, 4, 3, 4, 33]), vec![1002, 4, 3, 4, 99]);
+    assert_eq!(memory_after(&[1101, 100, -1, 4, 0]), vec![1101, 100, -1, 4, 99]);
+    // Position, immediate and relative reads of the same value
+    assert_eq!(run_prog(vec![109, 8, 4, 9, 104, 9, 204, 1, 99, 42], &[]), vec![42, 9, 42]);
+}
+
+#[test]
+#[should_panic(expected = "Cannot write to immediate")]
+fn immediate_write_panics() {
+    run_prog(vec![11101, 1, 1, 0, 99], &[]);
+}
+
+#[test]
+#[should_panic(expected = "Invalid root")]
+fn unknown_opcode_panics() {
+    run_prog(vec![42, 99], &[]);
+}
+
+#[test]
+fn relative_base_accumulates() {
+    // 109,10 then 109,-3 leaves the base at 7
+    assert_eq!(run_prog(vec![109, 10, 109, -3, 204, 0, 99, 1234], &[]), vec![1234]);
+}
+
+#[test]
+fn relative_base_from_memory() {
+    // The base adjustment itself can be a relative parameter
+    assert_eq!(run_prog(vec![109, 9, 209, 0, 204, -3, 99, 0, 0, 3], &[]), vec![3]);
+}
+
+#[test]
+fn relative_writes() {
+    assert_eq!(run_prog(vec![109, 20, 203, -5, 204, -5, 99], &[77]), vec![77]);
+    assert_eq!(run_prog(vec![109, 20, 21101, 2, 3, 0, 204, 0, 99], &[]), vec![5]);
+}
+
+#[test]
+fn memory_past_program_is_zero() {
+    assert_eq!(run_prog(vec![4, 5000, 1101, 1, 2, 5000, 4, 5000, 99], &[]), vec![0, 3]);
+}
+
+#[test]
+fn quine() {
+    let prog = parse_input(Path::new("./quine.txt"));
+    assert_eq!(run_prog(prog.clone(), &[]), prog);
+}
+
+#[test]
+fn large_numbers() {
+    assert_eq!(run_prog(vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]), vec![1219070632396864]);
+    assert_eq!(run_prog(vec![104, 1125899906842624, 99], &[]), vec![1125899906842624]);
+}
+
+#[test]
+fn day2_examples() {
+    assert_eq!(memory_after(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]), vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]);
+    assert_eq!(memory_after(&[1, 1, 1, 4, 99, 5, 6, 0, 99]), vec![30, 1, 1, 4, 2, 5, 6, 0, 99]);
+}
+
+#[test]
+fn day5_comparison_examples() {
+    type Case = (&'static [i64], fn(i64) -> bool);
+
+    let cases: &[Case] = &[
+        (&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], |n| n == 8),
+        (&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], |n| n < 8),
+        (&[3, 3, 1108, -1, 8, 3, 4, 3, 99], |n| n == 8),
+        (&[3, 3, 1107, -1, 8, 3, 4, 3, 99], |n| n < 8),
+    ];
+
+    for (prog, expected) in cases {
+        for n in 5..12 {
+            assert_eq!(run_prog(prog.to_vec(), &[n]), vec![expected(n) as i64], "{:?} with {}", prog, n);
+        }
+    }
+}
+
+#[test]
+fn day5_jump_examples() {
+    let progs: &[&[i64]] = &[
+        &[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+        &[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+    ];
+
+    for prog in progs {
+        assert_eq!(run_prog(prog.to_vec(), &[0]), vec![0]);
+        assert_eq!(run_prog(prog.to_vec(), &[-3]), vec![1]);
+    }
+
+    let prog = vec![
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+        1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+        999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+    ];
+
+    assert_eq!(run_prog(prog.clone(), &[7]), vec![999]);
+    assert_eq!(run_prog(prog.clone(), &[8]), vec![1000]);
+    assert_eq!(run_prog(prog, &[9]), vec![1001]);
+}
+
+#[test]
+fn day5_diagnostic() {
+    let file = loader::load(Path::new("./day5test.txt")).unwrap();
+    assert_eq!(file.checks[0].output, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 16348437]);
+    assert!(selftest::run_checks(&file, selftest::MAX_STEPS).iter().all(|r| r.verdict == selftest::Verdict::Pass));
+}
+
+// The day 7 part 1 chain, each amplifier's output feeding the next one's input
+fn try_sequence_state(program: Vec<i64>, sequence: &[i64]) -> i64 {
+    assert_eq!(sequence.len(), 5);
+
+    let mut output = 0;
+
+    for s in sequence.iter() {
+        let new_output = run_prog(program.clone(), &[*s, output]);
+        assert_eq!(new_output.len(), 1);
+        output = new_output[0];
+    }
+
+    output
+}
+
+#[test]
+fn day7_amplifier_examples() {
+    let prog = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+    assert_eq!(try_sequence_state(prog, &[4, 3, 2, 1, 0]), 43210);
+
+    let prog = vec![
+        3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23,
+        101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99, 0, 0,
+    ];
+    assert_eq!(try_sequence_state(prog, &[0, 1, 2, 3, 4]), 54321);
+
+    let prog = vec![
+        3, 31, 3, 32, 1002, 32, 10, 32, 1001, 31, -2, 31, 1007, 31, 0, 33,
+        1002, 33, 7, 33, 1, 33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0,
+    ];
+    assert_eq!(try_sequence_state(prog, &[1, 0, 4, 3, 2]), 65210);
+}
+
+#[test]
+fn day7_feedback_examples() {
+    let prog = vec![
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26,
+        27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+    ];
+    assert_eq!(try_sequence_feedback_in(&mut Pool::new(prog.clone()), &[9, 8, 7, 6, 5]), 139629729);
+    assert_eq!(find_highest(prog), 139629729);
+
+    let prog = vec![
+        3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54,
+        -5, 54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53,
+        55, 53, 4, 53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+    ];
+    assert_eq!(try_sequence_feedback_in(&mut Pool::new(prog.clone()), &[9, 7, 8, 5, 6]), 18216);
+    assert_eq!(find_highest(prog), 18216);
+}
+
+#[test]
+fn day9_boost() {
+    let boost = parse_input(Path::new("./input.txt"));
+    assert_eq!(run_prog(boost.clone(), &[1]), vec![4234906522]);
+    assert_eq!(run_prog(boost, &[2]), vec![60962]);
+}
+
+#[test]
+fn extension_opcode() {
+    let mut ext = Extensions::new();
+    // 20: dest = a * 10 + b
+    ext.register(20, 3, &[2], |c| {
+        let value = c.arg(0) * 10 + c.arg(1);
+        c.set(2, value);
+    });
+    // 21: output the next input plus one
+    ext.register_input(21, 0, &[], 1, |c| {
+        let value = c.input() + 1;
+        c.output(value);
+    });
+
+    let prog = vec![1120, 4, 5, 50, 4, 50, 21, 104, 7, 99];
+    assert_eq!(run_prog_with(prog, &[41], &ext), vec![45, 42, 7]);
+}
+
+#[test]
+fn extension_jump_and_base() {
+    let mut ext = Extensions::new();
+    // 30: jump to a, moving the relative base by b
+    ext.register(30, 2, &[], |c| {
+        *c.relative_base += c.arg(1);
+        let target = c.arg(0) as usize;
+        c.jump(target);
+    });
+
+    let prog = vec![1130, 6, 3, 104, 1, 99, 204, 0, 99];
+    assert_eq!(run_prog_with(prog, &[], &ext), vec![104]);
+}
+
+#[test]
+#[should_panic(expected = "more input than the 0 it declared")]
+fn extension_must_declare_input() {
+    let mut ext = Extensions::new();
+    ext.register(21, 0, &[], |c| {
+        let value = c.input();
+        c.output(value);
+    });
+
+    run_prog_with(vec![21, 99], &[1], &ext);
+}
+
+#[test]
+fn extension_input_waits_async() {
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+
+    let mut ext = Extensions::new();
+    // 22: output the sum of the next two inputs
+    ext.register_input(22, 0, &[], 2, |c| {
+        let value = c.input() + c.input();
+        c.output(value);
+    });
+
+    let mut pool = Pool::new(vec![22, 22, 99]);
+    pool.extensions = ext;
+    let mut state = pool.checkout(&[], &[]);
+
+    let (input, machine_in) = mpsc::unbounded();
+    let (machine_out, results) = mpsc::unbounded();
+
+    for value in [1, 2, 30, 40].iter() {
+        input.unbounded_send(*value).unwrap();
+    }
+    drop(input);
+
+    let (exit, results) = futures::executor::block_on(futures::future::join(
+        machine::run_async(&mut state, machine_in, machine_out),
+        results.collect::<Vec<_>>(),
+    ));
+
+    assert_eq!(exit, machine::Exit::Halted);
+    assert_eq!(results, vec![3, 70]);
+
+    // With only one of the two inputs the opcode waits instead of running
+    let mut state = pool.checkout(&[], &[5]);
+    let (_, machine_in) = mpsc::unbounded();
+    let (machine_out, _results) = mpsc::unbounded();
+    let exit = futures::executor::block_on(machine::run_async(&mut state, machine_in, machine_out));
+    assert_eq!((exit, state.pc), (machine::Exit::InputClosed, 0));
+}
+
+#[test]
+#[should_panic(expected = "already a builtin")]
+fn extension_cannot_replace_builtin() {
+    Extensions::new().register(1, 3, &[2], |_| {});
+}
+
+#[test]
+fn extension_stdlib() {
+    // echo, div, mod, then a call through push and ret, then push and pop
+    let prog = vec![109, 100, 15, 1110, 17, 5, 50, 1111, 17, 5, 51, 4, 50, 4, 51, 112, 21, 14, 104, -1, 99, 112, 7, 13, 52, 4, 52, 99];
+    assert_eq!(run_prog_with(prog, &[42], &extension::stdlib()), vec![42, 3, 2, 7]);
+}
+
+#[test]
+#[should_panic(expected = "Stack underflow")]
+fn extension_stdlib_underflow() {
+    run_prog_with(vec![14], &[], &extension::stdlib());
+}
+
+#[test]
+#[should_panic(expected = "Cannot divide 7 by 0")]
+fn extension_stdlib_divide_by_zero() {
+    run_prog_with(vec![1110, 7, 0, 0, 99], &[], &extension::stdlib());
+}
+
+#[test]
+#[should_panic(expected = "Cannot divide -9223372036854775808 by -1")]
+fn extension_stdlib_mod_overflow() {
+    run_prog_with(vec![1111, i64::MIN, -1, 0, 99], &[], &extension::stdlib());
+}
+
+#[test]
+fn optimize_multiply_by_one() {
+    let prog = vec![1002, 6, 1, 7, 4, 7, 99, 0];
+    assert_eq!(optimize::optimize(&prog, &Default::default()), vec![1001, 6, 0, 7, 4, 7, 99, 0]);
+}
+
+#[test]
+fn optimize_constant_folding() {
+    let prog = vec![1107, 6, 7, 7, 4, 7, 99, 0];
+    assert_eq!(optimize::optimize(&prog, &Default::default()), vec![1101, 1, 0, 7, 4, 7, 99, 0]);
+}
+
+#[test]
+fn optimize_removes_empty_jumps() {
+    let prog = vec![1002, 11, 1, 12, 1105, 1, 7, 4, 12, 99, 0, 6, 0];
+    let optimized = optimize::optimize(&prog, &Default::default());
+    assert_eq!(optimized, vec![1001, 8, 0, 9, 4, 9, 99, 0, 6, 0]);
+    assert_eq!(run_prog(optimized, &[]), vec![6]);
+}
+
+#[test]
+fn optimize_leaves_modified_code() {
+    // The add rewrites the multiply's operand, so the multiply must stay as written
+    let prog = vec![1, 10, 10, 6, 1002, 9, 1, 9, 4, 9, 99];
+    assert_eq!(optimize::optimize(&prog, &Default::default()), prog);
+}
+
+#[test]
+fn optimize_relative_needs_trust() {
+    let prog = vec![109, 10, 1102, 3, 4, 7, 204, 0, 99, 0, 0];
+    assert_eq!(optimize::optimize(&prog, &Default::default()), prog);
+
+    let trusted = optimize::optimize(&prog, &optimize::OptimizeOptions { trust_relative: true });
+    assert_eq!(trusted, vec![109, 10, 1101, 12, 0, 7, 204, 0, 99, 0, 0]);
+}
+
+#[test]
+fn disassemble_sweeps_data() {
+    assert_eq!(disasm::disassemble(&[1001, 4, -1, 4, 204, 3, 99, 42]), "     0: add [4], -1, [4]\n     4: out [rb+3]\n     6: hlt\n     7: data 42\n");
+}
+
+#[test]
+fn decompile_if_else_and_loop() {
+    // Counts m[20] down from its input, printing 1 for odd values and 0 for even ones
+    let prog = vec![
+        3, 20,
+        1008, 20, 0, 21, 1005, 21, 40,
+        1101, 0, 0, 22,
+        1007, 20, 2, 23, 1005, 23, 30,
+        1001, 20, -2, 20, 1105, 1, 13, 0, 0, 0,
+        1008, 20, 1, 22, 4, 22, 1101, 0, 0, 24,
+        99,
+    ];
+
+    let expected = "fn main() {
+    m[20] = input();
+    m[21] = m[20] == 0;
+    if (m[21] == 0) {
+        m[22] = 0;
+        loop {
+            m[23] = m[20] < 2;
+            if (m[23] != 0) goto L30;
+            m[20] = m[20] - 2;
+        }
+L30:
+        m[22] = m[20] == 1;
+        output(m[22]);
+        m[24] = 0;
+    }
+    halt();
+}
+";
+
+    assert_eq!(decompile::decompile(&prog), expected);
+}
+
+#[test]
+fn decompile_boost_calls() {
+    let boost = parse_input(Path::new("./input.txt"));
+    let pseudo = decompile::decompile(&boost);
+    assert!(pseudo.contains("fn f920() {"));
+    assert!(pseudo.contains("        f920();\n"));
+    assert!(pseudo.contains("    return;\n"));
+}
+
+#[test]
+fn coverage_classifies_memory() {
+    // Reads m[9], overwrites the add's own operand, and leaves m[10] alone
+    let prog = vec![1001, 9, 5, 2, 4, 9, 99, 0, 0, 7, 0];
+    let (output, coverage) = coverage::run_with_coverage(prog, &[]);
+
+    assert_eq!(output, vec![7]);
+    assert_eq!(coverage.kind(0), coverage::Kind::Code);
+    assert_eq!(coverage.kind(2), coverage::Kind::ModifiedCode);
+    assert_eq!(coverage.kind(7), coverage::Kind::Unused);
+    assert_eq!(coverage.kind(9), coverage::Kind::Data);
+    assert_eq!(coverage.read[9], 2);
+    assert_eq!(coverage.executed[6], 1);
+
+    let report = coverage.report(11);
+    assert!(report.starts_with("    addresses           kind   executed      reads     writes\n          0-1           code          2          0          0\n"));
+    assert!(coverage.svg(11, 4).contains("<title>9: data (exec 0, read 2, write 0)</title>"));
+}
+
+#[test]
+fn pool_resets_machines() {
+    // Doubles m[5] into m[0], then outputs m[5], which is also the output's own operand
+    let mut pool = Pool::new(vec![1, 5, 5, 0, 104, 0, 99]);
+
+    let configs = (1..4).map(|n| Config { overrides: vec![(5, n)], input: Vec::new() }).collect::<Vec<_>>();
+    let results = pool.batch(&configs, |state, output, halted| (state.program[0], output.to_vec(), halted));
+
+    assert_eq!(results, vec![Ok((2, vec![1], true)), Ok((4, vec![2], true)), Ok((6, vec![3], true))]);
+    assert_eq!(pool.checkout(&[], &[]).program[..7], [1, 5, 5, 0, 104, 0, 99]);
+}
+
+#[test]
+fn pool_step_limit() {
+    let mut pool = Pool::new(vec![1105, 1, 0]);
+    pool.max_steps = 10;
+    assert_eq!(pool.evaluate(&Config::default(), |_, _, halted| halted), Ok(false));
+}
+
+#[test]
+fn pool_faults_are_per_config() {
+    // Outputs the sum of two inputs
+    let mut pool = Pool::new(vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99]);
+
+    let configs = vec![
+        Config { overrides: Vec::new(), input: vec![1, 2] },
+        Config { overrides: Vec::new(), input: vec![5] },
+        Config { overrides: vec![(4, 42)], input: vec![1, 2] },
+        Config { overrides: Vec::new(), input: vec![3, 4] },
+    ];
+    let results = pool.batch(&configs, |_, output, halted| (output.to_vec(), halted));
+
+    assert_eq!(results[0], Ok((vec![3], true)));
+    assert_eq!(results[1], Err(pool::Fault::NeedsInput(2)));
+    assert_eq!(results[1].as_ref().unwrap_err().to_string(), "faulted: out of input at pc 2");
+    assert!(matches!(&results[2], Err(pool::Fault::Panicked(message)) if message.contains("42")), "{:?}", results[2]);
+    assert_eq!(results[3], Ok((vec![7], true)));
+}
+
+#[test]
+fn day2_noun_and_verb() {
+    let program = parse_input(Path::new("../day2/data.txt"));
+    assert_eq!(find_end_value(program, 19690720), Some((82, 98)));
+}
+
+#[test]
+fn sweep_finds_earliest_match() {
+    // Outputs a * b from two inputs
+    let prog = vec![3, 20, 3, 21, 2, 20, 21, 22, 4, 22, 99];
+
+    for threads in 1..5 {
+        let found = Sweep::new()
+            .input(0, 0..10)
+            .input(1, 0..10)
+            .threads(threads)
+            .find_first(&prog, |outcome| outcome.output == [12]);
+        assert_eq!(found, Some(vec![2, 6]));
+    }
+
+    let none = Sweep::new().input(0, 0..3).input(1, 0..3).find_first(&prog, |outcome| outcome.output == [12]);
+    assert_eq!(none, None);
+}
+
+#[test]
+fn sweep_maximizes_objective() {
+    // m[0] = m[22] - m[20] * m[20], with both patched in
+    let prog = vec![2, 20, 20, 21, 1002, 21, -1, 21, 1, 22, 21, 0, 99];
+
+    let best = Sweep::new()
+        .patch(20, -5..6)
+        .patch(22, 0..3)
+        .threads(3)
+        .maximize(&prog, |outcome| Some(outcome.memory[0]));
+    assert_eq!(best, Some((vec![0, 2], 2)));
+}
+
+#[test]
+fn sweep_respects_step_limit() {
+    // Loops forever unless the patched cell is zero
+    let prog = vec![1005, 5, 0, 99, 0, 0];
+    let found = Sweep::new()
+        .patch(5, -3..3)
+        .max_steps(100)
+        .find_first(&prog, |outcome| outcome.halted);
+    assert_eq!(found, Some(vec![0]));
+}
+
+#[test]
+fn sweep_patches_past_padded_memory() {
+    // Outputs the patched cell, which is past the 10000 words every image is padded to
+    let prog = vec![4, 20000, 99];
+    let found = Sweep::new()
+        .patch(20000, 0..5)
+        .threads(2)
+        .find_first(&prog, |outcome| outcome.output == [3]);
+    assert_eq!(found, Some(vec![3]));
+}
+
+#[test]
+fn event_stream_lines() {
+    let mut trace = Vec::new();
+    let output = events::run_traced(vec![3, 10, 1001, 10, 1, 10, 204, 10, 99], &[41], &mut trace).unwrap();
+    assert_eq!(output, vec![42]);
+
+    let trace = String::from_utf8(trace).unwrap();
+    let lines = trace.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], r#"{"step":0,"pc":0,"opcode":3,"op":"in","relative_base":0,"reads":[],"writes":[[10,41]],"input":[41],"output":null,"halted":false}"#);
+    assert_eq!(lines[1], r#"{"step":1,"pc":2,"opcode":1001,"op":"add","relative_base":0,"reads":[[10,41]],"writes":[[10,42]],"input":[],"output":null,"halted":false}"#);
+    assert_eq!(lines[2], r#"{"step":2,"pc":6,"opcode":204,"op":"out","relative_base":0,"reads":[[10,42]],"writes":[],"input":[],"output":42,"halted":false}"#);
+    assert_eq!(lines[3], r#"{"step":3,"pc":8,"opcode":99,"op":"hlt","relative_base":0,"reads":[],"writes":[],"input":[],"output":null,"halted":true}"#);
+}
+
+#[test]
+fn remote_session_commands() {
+    // Adds pairs of inputs forever
+    let mut session = remote::Session::new(vec![3, 20, 3, 21, 1, 20, 21, 22, 4, 22, 1105, 1, 0]);
+    let mut send = |line: &str| session.handle(line).unwrap();
+
+    assert_eq!(send("run"), "ok waiting");
+    assert_eq!(send("state"), "ok pc=0 rb=0 status=waiting inputs=0 outputs=0");
+    assert_eq!(send("input 2,3,10"), "ok");
+    assert_eq!(send("next"), "ok 5");
+    assert_eq!(send("step 2"), "ok running 2");
+    assert_eq!(send("step 5"), "ok waiting 0");
+    assert_eq!(send("peek 20"), "ok 10");
+    assert_eq!(send("input 4"), "ok");
+    assert_eq!(send("run"), "ok waiting");
+    assert_eq!(send("output"), "ok 14");
+    assert_eq!(send("output"), "ok");
+    assert_eq!(send("poke 0 99"), "ok");
+    assert_eq!(send("run"), "ok halted");
+    assert_eq!(send("peek 99999"), "err bad address 99999");
+    assert_eq!(send("reset"), "ok");
+    assert_eq!(send("peek 0"), "ok 3");
+    assert_eq!(send("frobnicate"), r#"err unknown command "frobnicate""#);
+    assert_eq!(session.handle("quit"), None);
+}
+
+#[test]
+fn remote_reports_faults() {
+    let mut session = remote::Session::new(vec![42]);
+    assert_eq!(session.handle("run").unwrap(), "err faulted: Invalid root 42");
+}
+
+#[test]
+fn remote_run_stops_at_step_limit() {
+    // Jumps to itself forever
+    let mut session = remote::Session::new(vec![1105, 1, 0]);
+    session.max_steps = 1000;
+
+    assert_eq!(session.handle("run").unwrap(), "ok running 1000");
+    assert_eq!(session.handle("next").unwrap(), "ok running 1000");
+    assert_eq!(session.handle("state").unwrap(), "ok pc=0 rb=0 status=running inputs=0 outputs=0");
+    assert_eq!(session.handle("reset").unwrap(), "ok");
+    assert_eq!(session.handle("run").unwrap(), "ok running 1000");
+}
+
+#[test]
+fn remote_over_tcp() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        remote::serve_connection(stream.try_clone().unwrap(), stream, vec![3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]).unwrap();
+    });
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    stream.write_all(b"input 14\nrun\noutput\nquit\n").unwrap();
+
+    let replies = BufReader::new(stream).lines().map(|l| l.unwrap()).collect::<Vec<_>>();
+    assert_eq!(replies, vec!["ok", "ok halted", "ok 42"]);
+
+    server.join().unwrap();
+}
+
+#[test]
+fn watchdog_catches_jump_to_self() {
+    let stuck = watchdog::run_watched(vec![1105, 1, 0], &[]).unwrap_err();
+    assert_eq!((stuck.start, stuck.end, stuck.period), (0, 3, 1));
+}
+
+#[test]
+fn watchdog_lets_counting_loop_finish() {
+    let program = vec![1001, 20, 1, 20, 1007, 20, 5, 21, 1005, 21, 0, 4, 20, 99];
+    assert_eq!(watchdog::run_watched(program, &[]), Ok(vec![5]));
+}
+
+#[test]
+fn watchdog_catches_silent_spin_after_output() {
+    let stuck = watchdog::run_watched(vec![104, 7, 104, 8, 1105, 1, 4], &[]).unwrap_err();
+    assert_eq!((stuck.start, stuck.end, stuck.period), (4, 7, 1));
+}
+
+#[test]
+fn watchdog_catches_long_loops() {
+    // Counts m20 up to 1000, resets it and starts again, forever
+    let program = vec![1001, 20, 1, 20, 1008, 20, 1000, 21, 1006, 21, 0, 1101, 0, 0, 20, 1105, 1, 0];
+    let stuck = watchdog::run_watched(program, &[]).unwrap_err();
+    assert_eq!((stuck.start, stuck.end, stuck.period), (0, 18, 3002));
+
+    // Only caught once the whole count repeats, so never from a single pass
+    assert!(stuck.step > 3002 && stuck.step < 4 * 3002, "{}", stuck.step);
+}
+
+#[test]
+fn watchdog_ignores_loops_that_read_input() {
+    // Reads forever, but every iteration consumes input so it can't be proven stuck
+    let program = vec![3, 20, 1005, 20, 0, 99];
+    assert_eq!(watchdog::run_watched(program, &[1, 1, 1, 0]), Ok(vec![]));
+}
+
+#[test]
+fn dialects_accept_their_own_programs() {
+    let mut day2 = parse_input(Path::new("../day2/data.txt"));
+    day2[1] = 82;
+    day2[2] = 98;
+    assert!(dialect::run_prog_in(day2, &[], Dialect::Day2, &Extensions::new()).is_ok());
+
+    let day5 = parse_input(Path::new("./day5test.txt"));
+    assert_eq!(dialect::run_prog_in(day5, &[1], Dialect::Day5, &Extensions::new()).unwrap().last(), Some(&16348437));
+
+    let boost = parse_input(Path::new("./input.txt"));
+    assert_eq!(dialect::run_prog_in(boost, &[2], Dialect::Day9, &Extensions::new()), Ok(vec![60962]));
+}
+
+#[test]
+fn dialects_reject_newer_features() {
+    use dialect::{DialectError, Feature};
+
+    let err = dialect::run_prog_in(vec![3, 0, 99], &[1], Dialect::Day2, &Extensions::new()).unwrap_err();
+    assert_eq!(err, DialectError { pc: 0, dialect: Dialect::Day2, needs: Dialect::Day5, feature: Feature::Opcode(OpcodeRoot::Input) });
+    assert_eq!(err.to_string(), "Opcode 3 (in) at 0 needs day5, but the program is day2");
+
+    let err = dialect::run_prog_in(vec![1, 0, 0, 0, 1101, 1, 2, 0, 99], &[], Dialect::Day2, &Extensions::new()).unwrap_err();
+    assert_eq!(err.feature, Feature::Mode(0, ParamMode::Immediate));
+    assert_eq!(err.pc, 4);
+
+    let boost = parse_input(Path::new("./input.txt"));
+    let err = dialect::run_prog_in(boost, &[2], Dialect::Day5, &Extensions::new()).unwrap_err();
+    assert_eq!(err.needs, Dialect::Day9);
+}
+
+#[test]
+fn dialects_apply_to_every_runner() {
+    let mut pool = Pool::new(vec![109, 1, 99]);
+    pool.dialect = Dialect::Day5;
+    match pool.evaluate(&Config::default(), |_, _, _| ()) {
+        Err(pool::Fault::Panicked(message)) => assert!(message.ends_with("needs day9, but the program is day5"), "{}", message),
+        other => panic!("Expected a dialect fault, got {:?}", other),
+    }
+}
+
+#[test]
+fn loader_accepts_mixed_separators_and_comments() {
+    let text = "# doubles its input\n3,9 # read\n  102 2\t9,9\n4,9,\n99\n";
+    assert_eq!(loader::parse_text(text).unwrap().code, vec![3, 9, 102, 2, 9, 9, 4, 9, 99]);
+}
+
+#[test]
+fn loader_reads_metadata() {
+    let text = "#! name: doubler\n#! input: 3\n#! output: 6\n#! input: -4\n#! output: -8\n3,9,102,2,9,9,4,9,99\n";
+    let file = loader::parse_text(text).unwrap();
+
+    assert_eq!(file.name.as_deref(), Some("doubler"));
+    assert_eq!(file.checks, vec![
+        loader::Check { input: vec![3], output: vec![6] },
+        loader::Check { input: vec![-4], output: vec![-8] },
+    ]);
+    assert_eq!(run_prog(file.code.clone(), &[-4]), vec![-8]);
+}
+
+#[test]
+fn loader_reports_line_and_column() {
+    let error = |text: &str| loader::parse_text(text).unwrap_err().to_string();
+
+    assert_eq!(error("1,2,3\n4, x5,6\n"), r#"2:4: Invalid value "x5""#);
+    assert_eq!(error("#! input: 1, two\n99"), r#"1:14: Invalid value "two""#);
+    assert_eq!(error("#! author: me\n99"), r#"1:4: Unknown metadata key "author""#);
+    assert_eq!(error("#! output: 1\n99"), "1:3: `output` needs an `input` before it");
+    assert_eq!(error("99\n#! name: late"), "2:1: Metadata must come before the program");
+}
+
+#[test]
+fn loader_round_trips_binary() {
+    let mut file = loader::load(Path::new("./input.txt")).unwrap();
+    file.name = Some("BOOST".to_string());
+    file.checks.push(loader::Check { input: vec![2], output: vec![60962] });
+    file.code.extend(&[0, -1, i64::MAX, i64::MIN]);
+
+    let binary = loader::encode_binary(&file);
+    assert!(binary.len() < file.code.len() * 3);
+    assert_eq!(loader::parse(&binary).unwrap(), file);
+
+    let truncated = loader::parse(&binary[..binary.len() - 1]).unwrap_err();
+    assert!(truncated.to_string().contains("Truncated value"));
+}
+
+#[test]
+fn loader_round_trips_extreme_values() {
+    let file = loader::ProgramFile { name: None, checks: Vec::new(), code: vec![i64::MIN, i64::MAX, 99] };
+    let binary = loader::encode_binary(&file);
+    assert_eq!(loader::parse(&binary).unwrap(), file);
+
+    let error = |data: &[u8]| loader::parse(data).unwrap_err().to_string();
+
+    // i64::MIN is nine 0xff bytes then 0x01; anything more in the last byte overflows
+    assert_eq!(error(b"\0icb\0\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02"), "byte 5: Value longer than 64 bits");
+    assert_eq!(error(b"\0icb\0\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f"), "byte 5: Value longer than 64 bits");
+    assert_eq!(error(b"\0icb\0\xff\xff\xff\xff\xff\xff\xff\xff\xff\x81\x00"), "byte 5: Value longer than 64 bits");
+}
+
+#[test]
+fn loader_rejects_bad_metadata_lengths() {
+    let error = |data: &[u8]| loader::parse(data).unwrap_err().to_string();
+
+    assert_eq!(error(b"\0icb\x01"), "byte 4: Negative metadata length -1");
+    // The largest positive length, which would overflow the end of the header
+    assert_eq!(error(b"\0icb\xfe\xff\xff\xff\xff\xff\xff\xff\xff\x01"), "byte 14: Bad metadata");
+    assert_eq!(error(b"\0icb\x10ab"), "byte 5: Bad metadata");
+}
+
+#[test]
+fn annotated_programs_pass() {
+    let report = selftest::test_dir(Path::new("."), selftest::MAX_STEPS).unwrap();
+    assert_eq!((report.passed(), report.failed()), (5, 0), "{}", report.summary());
+}
+
+#[test]
+fn selftest_reports_failures() {
+    use selftest::Verdict;
+
+    let dir = std::env::temp_dir().join(format!("day9-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("double.txt"), "#! input: 2\n#! output: 4\n#! input: 3\n#! output: 7\n3,9,102,2,9,9,4,9,99").unwrap();
+    std::fs::write(dir.join("broken.txt"), "#! input:\n#! output:\n1,2,x").unwrap();
+    std::fs::write(dir.join("spin.txt"), "#! input:\n#! output:\n1105,1,0").unwrap();
+    std::fs::write(dir.join("plain.txt"), "99").unwrap();
+
+    let report = selftest::test_dir(&dir, 1000).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let names = report.programs.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["broken.txt", "double.txt", "spin.txt"]);
+
+    let verdicts = report.programs[1].1.as_ref().unwrap().iter().map(|r| r.verdict.clone()).collect::<Vec<_>>();
+    assert_eq!(verdicts, vec![Verdict::Pass, Verdict::Fail(vec![6])]);
+    assert_eq!(report.programs[2].1.as_ref().unwrap()[0].verdict, Verdict::Error("did not halt within 1000 steps".to_string()));
+    assert_eq!((report.passed(), report.failed()), (1, 3));
+
+    let summary = report.summary();
+    assert!(summary.contains(r#"FAIL broken.txt: 3:5: Invalid value "x""#), "{}", summary);
+    assert!(summary.contains("FAIL double.txt #2 (input 3): expected 7, got 6"), "{}", summary);
+}
+
+#[test]
+fn async_machines_compose_with_channels() {
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+
+    // Adds one to each input until it reads a zero
+    let program = vec![3, 20, 1006, 20, 14, 1001, 20, 1, 21, 4, 21, 1105, 1, 0, 99];
+    assert_eq!(run_prog(program.clone(), &[5, 0]), vec![6]);
+
+    let mut pool = Pool::new(program);
+
+    let (mut first, mut second) = (pool.checkout(&[], &[]), pool.checkout(&[], &[]));
+    let (input, first_in) = mpsc::unbounded();
+    let (first_out, second_in) = mpsc::unbounded();
+    let (second_out, results) = mpsc::unbounded();
+
+    for value in [1, 10, 100].iter() {
+        input.unbounded_send(*value).unwrap();
+    }
+    drop(input);
+
+    let (exits, results) = futures::executor::block_on(futures::future::join(
+        futures::future::join(
+            machine::run_async(&mut first, first_in, first_out),
+            machine::run_async(&mut second, second_in, second_out),
+        ),
+        results.collect::<Vec<_>>(),
+    ));
+
+    assert_eq!(exits, (machine::Exit::InputClosed, machine::Exit::InputClosed));
+    assert_eq!(results, vec![3, 12, 102]);
+}
+
+#[test]
+fn sweep_skips_faulting_points() {
+    // Outputs its input, unless that's under 3, when it reads another
+    let prog = vec![3, 20, 1007, 20, 3, 21, 1005, 21, 12, 4, 20, 99, 3, 22, 99];
+
+    for threads in 1..4 {
+        let sweep = Sweep::new().input(0, 0..6).threads(threads);
+        assert_eq!(sweep.find_first(&prog, |outcome| outcome.halted), Some(vec![3]));
+        assert_eq!(sweep.find_first(&prog, |outcome| outcome.output == [1]), None);
+        assert_eq!(sweep.maximize(&prog, |outcome| outcome.output.last().copied()), Some((vec![5], 5)));
+    }
+
+    // Opcodes 97 and 98 don't exist, and 99 halts without output
+    let sweep = Sweep::new().patch(9, 97..100).base_input(&[4]);
+    assert_eq!(sweep.maximize(&prog, |outcome| Some(outcome.output.len() as i64)), Some((vec![99], 0)));
+}
+
+#[test]
+fn sweep_dimension_specs() {
+    // Outputs a * b from two inputs
+    let prog = vec![3, 20, 3, 21, 2, 20, 21, 22, 4, 22, 99];
+    let sweep = Sweep::new().dimension("in:0=0..10").and_then(|s| s.dimension("in:1=-3..3")).unwrap();
+    assert_eq!(sweep.len(), 60);
+    assert_eq!(sweep.find_first(&prog, |outcome| outcome.output == [-6]), Some(vec![2, -3]));
+
+    let scaled = Sweep::new().dimension("in:1=-2..3").unwrap().base_input(&[4, 0]);
+    assert_eq!(scaled.maximize(&prog, |outcome| outcome.output.last().copied()), Some((vec![2], 8)));
+
+    // Outputs the patched cell
+    let patched = Sweep::new().dimension("mem:5=3..7").unwrap();
+    assert_eq!(patched.maximize(&[4, 5, 99, 0, 0, 0], |outcome| outcome.output.last().copied()), Some((vec![6], 6)));
+
+    for bad in ["in:0", "mem:x=0..1", "reg:0=0..1", "in:0=1-2"].iter() {
+        assert!(Sweep::new().dimension(bad).is_err(), "{}", bad);
+    }
+}
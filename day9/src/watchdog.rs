@@ -0,0 +1,164 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::{pad_memory, step_access, step_prog, ProgramState};
+use crate::dialect::Dialect;
+use crate::extension::Extensions;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfiniteLoop {
+    // The lowest pc in the loop and one past the end of its highest instruction
+    pub start: usize,
+    pub end: usize,
+    // Steps per iteration, and the step at which the repeat was confirmed
+    pub period: u64,
+    pub step: u64,
+}
+
+// One memory cell's share of the memory hash. Cells are combined with xor,
+// so a write only has to swap out the old share for the new one.
+fn cell_hash(addr: usize, value: i64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (addr, value).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn memory_hash(program: &[i64]) -> u64 {
+    program.iter().enumerate().fold(0, |hash, (addr, &value)| hash ^ cell_hash(addr, value))
+}
+
+// The machine state a loop has to return to, with memory as its hash
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Checkpoint {
+    pc: usize,
+    relative_base: i64,
+    memory: u64,
+}
+
+// Watches a machine for a state it has been in before with no input or output
+// since, which means it's stuck for good: nothing outside it can change what
+// happens next.
+//
+// Only states after a backwards (or in-place) jump are checked, against a
+// single checkpoint that moves forward after 1, 2, 4, ... steps (Brent's
+// cycle detection), so memory use stays constant however long the machine
+// runs. Memory is hashed incrementally as it's written, so checking a state
+// costs the same whatever the size of memory. Custom opcodes can write
+// anywhere, so memory is hashed again in full after each one.
+pub struct Watchdog {
+    memory: Option<u64>,
+    checkpoint: Option<(Checkpoint, u64)>,
+    // Steps the checkpoint stays put for before moving
+    patience: u64,
+    step: u64,
+}
+
+impl Default for Watchdog {
+    fn default() -> Watchdog {
+        Watchdog { memory: None, checkpoint: None, patience: 1, step: 0 }
+    }
+}
+
+impl Watchdog {
+    pub fn new() -> Watchdog {
+        Watchdog::default()
+    }
+
+    // Runs one step, like step_prog, or reports the loop the machine is stuck in
+    pub fn step(&mut self, state: &mut ProgramState) -> Result<(Option<i64>, bool), InfiniteLoop> {
+        let memory = self.memory.get_or_insert_with(|| memory_hash(&state.program));
+        let custom = state.extensions.get((state.program[state.pc] % 100) as u32).is_some();
+
+        let mut writes = step_access(state).writes;
+        writes.sort_unstable();
+        writes.dedup();
+
+        for &addr in writes.iter() {
+            *memory ^= cell_hash(addr, state.program[addr]);
+        }
+
+        let (old_pc, old_input) = (state.pc, state.input_idx);
+        let (out, halted) = step_prog(state);
+
+        if custom {
+            *memory = memory_hash(&state.program);
+        } else {
+            for &addr in writes.iter() {
+                *memory ^= cell_hash(addr, state.program[addr]);
+            }
+        }
+
+        self.step += 1;
+
+        if out.is_some() || halted || state.input_idx != old_input {
+            self.checkpoint = None;
+            self.patience = 1;
+        } else if state.pc <= old_pc {
+            self.check(state)?;
+        }
+
+        Ok((out, halted))
+    }
+
+    fn check(&mut self, state: &ProgramState) -> Result<(), InfiniteLoop> {
+        let now = Checkpoint { pc: state.pc, relative_base: state.relative_base, memory: self.memory.unwrap() };
+
+        match self.checkpoint {
+            Some((then, step)) if then == now => {
+                if let Some(stuck) = self.confirm(state, self.step - step) {
+                    return Err(stuck);
+                }
+            }
+            Some((_, step)) if self.step - step < self.patience => return Ok(()),
+            _ => self.patience *= 2,
+        }
+
+        self.checkpoint = Some((now, self.step));
+        Ok(())
+    }
+
+    // Hashes can collide, so replay one period on a copy and compare for real
+    fn confirm(&self, state: &ProgramState, period: u64) -> Option<InfiniteLoop> {
+        let mut copy = state.clone();
+        let (mut start, mut end) = (copy.pc, copy.pc);
+
+        for _ in 0..period {
+            let input_idx = copy.input_idx;
+            let access = step_access(&copy);
+
+            let (out, halted) = step_prog(&mut copy);
+            if out.is_some() || halted || copy.input_idx != input_idx {
+                return None;
+            }
+
+            start = start.min(access.pc);
+            end = end.max(access.pc + access.size);
+        }
+
+        let same = copy.pc == state.pc && copy.relative_base == state.relative_base && copy.program == state.program;
+
+        if same {
+            Some(InfiniteLoop { start, end, period, step: self.step })
+        } else {
+            None
+        }
+    }
+}
+
+pub fn run_watched(mut program: Vec<i64>, input: &[i64]) -> Result<Vec<i64>, InfiniteLoop> {
+    pad_memory(&mut program);
+
+    let mut state = ProgramState { pc: 0, input: input.to_vec(), input_idx: 0, program, relative_base: 0, extensions: Extensions::new(), dialect: Dialect::default() };
+    let mut watchdog = Watchdog::new();
+    let mut output = Vec::new();
+
+    loop {
+        let (out, halted) = watchdog.step(&mut state)?;
+
+        output.extend(out);
+
+        if halted {
+            return Ok(output);
+        }
+    }
+}